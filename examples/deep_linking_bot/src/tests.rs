@@ -57,8 +57,8 @@ async fn test_send_message() {
 
     assert_eq!(
         sent_message.text().unwrap(),
-        text::YOU_HAVE_A_NEW_MESSAGE.replace("{message}", "I love you!")
-    ); // Just checking that the text and sender are correct
+        "You have a new message!\n\nI love you!"
+    ); // Just checking that the text and sender are correct, the html is already parsed
     assert_eq!(sent_message.chat.id.0, 987654321);
 
     assert_eq!(
@@ -53,7 +53,7 @@ pub async fn bot_phrase(bot: Bot, msg: Message) -> HandlerResult {
 mod tests {
     use teloxide_tests::{MockBot, MockGroupChat, MockMessageText, MockUser};
 
-    use crate::{db, dptree::deps, get_bot_storage, handler_tree::handler_tree, text};
+    use crate::{db, dptree::deps, get_bot_storage, handler_tree::handler_tree};
 
     #[tokio::test]
     async fn test_phrase() {
@@ -84,16 +84,12 @@ mod tests {
         )
         .unwrap();
 
-        // Parse mode doesn't yet work, so it still has link text. But that isn't a problem!
-        bot.dispatch_and_check_last_text(
-            &format!(
-                "🤗 | {} hugged {}",
-                text::make_link("nick1".to_string(), 1234),
-                text::make_link("nick2".to_string(), 5678)
-            )
-            .to_string(),
-        )
-        .await;
+        // The links are parsed out of the html, just like telegram does it
+        bot.dispatch_and_check_last_text("🤗 | nick1 hugged nick2")
+            .await;
+        let responses = bot.get_responses();
+        let entities = responses.sent_messages.last().unwrap().entities().unwrap();
+        assert_eq!(entities.len(), 2);
     }
 
     #[tokio::test]
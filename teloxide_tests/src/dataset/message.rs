@@ -140,6 +140,30 @@ impl crate::dataset::IntoUpdate for MockEditedMessage {
     }
 }
 
+impl crate::dataset::IntoUpdate for Message {
+    /// Converts the already built Message into an updates vector
+    ///
+    /// # Example
+    /// ```
+    /// use teloxide_tests::IntoUpdate;
+    /// use teloxide::types::{UpdateId, UpdateKind};
+    /// use std::sync::atomic::AtomicI32;
+    ///
+    /// let message = teloxide_tests::MockMessageText::new().build();
+    /// let update = message.clone().into_update(&AtomicI32::new(42))[0].clone();
+    ///
+    /// assert_eq!(update.id, UpdateId(42));
+    /// assert_eq!(update.kind, UpdateKind::Message(message));
+    /// ```
+    ///
+    fn into_update(self, id: &AtomicI32) -> Vec<Update> {
+        vec![Update {
+            id: UpdateId(id.fetch_add(1, Ordering::Relaxed) as u32),
+            kind: UpdateKind::Message(self),
+        }]
+    }
+}
+
 // More messages like Webapp data is needed
 
 Message! {
@@ -284,3 +308,114 @@ impl MockMessageNewChatMembers {
             }))
     }
 }
+
+Message! {
+    #[derive(Changeable, Clone)]
+    pub struct MockMessageUsersShared {
+        pub request_id: RequestId,
+        pub users: Vec<SharedUser>,
+    }
+}
+
+impl MockMessageUsersShared {
+    pub const REQUEST_ID: i32 = 0;
+    pub const SHARED_USER_ID: u64 = 5678;
+
+    /// Creates a new easily changeable users shared message builder
+    ///
+    /// # Example
+    /// ```
+    /// let message = teloxide_tests::MockMessageUsersShared::new()
+    ///     .request_id(teloxide::types::RequestId(1))
+    ///     .build();
+    /// assert_eq!(message.shared_users().unwrap().request_id.0, 1);
+    /// ```
+    ///
+    pub fn new() -> Self {
+        Self::new_message(
+            RequestId(Self::REQUEST_ID),
+            vec![SharedUser {
+                user_id: UserId(Self::SHARED_USER_ID),
+                first_name: None,
+                last_name: None,
+                username: None,
+                photo: None,
+            }],
+        )
+    }
+
+    /// Builds the users shared message
+    ///
+    /// # Example
+    /// ```
+    /// let mock_message = teloxide_tests::MockMessageUsersShared::new();
+    /// let message = mock_message.build();
+    /// assert_eq!(message.shared_users().unwrap().users[0].user_id.0, teloxide_tests::MockMessageUsersShared::SHARED_USER_ID);  // SHARED_USER_ID is a default value
+    /// ```
+    ///
+    pub fn build(self) -> Message {
+        self.clone()
+            .build_message(MessageKind::UsersShared(MessageUsersShared {
+                users_shared: UsersShared {
+                    request_id: self.request_id,
+                    users: self.users,
+                },
+            }))
+    }
+}
+
+Message! {
+    #[derive(Changeable, Clone)]
+    pub struct MockMessageChatShared {
+        pub request_id: RequestId,
+        pub shared_chat_id: ChatId,
+        pub title: Option<String>,
+        pub username: Option<String>,
+    }
+}
+
+impl MockMessageChatShared {
+    pub const REQUEST_ID: i32 = 0;
+    pub const SHARED_CHAT_ID: i64 = -1005678;
+
+    /// Creates a new easily changeable chat shared message builder
+    ///
+    /// # Example
+    /// ```
+    /// let message = teloxide_tests::MockMessageChatShared::new()
+    ///     .title("Shared chat")
+    ///     .build();
+    /// assert_eq!(message.shared_chat().unwrap().title, Some("Shared chat".to_string()));
+    /// ```
+    ///
+    pub fn new() -> Self {
+        Self::new_message(
+            RequestId(Self::REQUEST_ID),
+            ChatId(Self::SHARED_CHAT_ID),
+            None,
+            None,
+        )
+    }
+
+    /// Builds the chat shared message
+    ///
+    /// # Example
+    /// ```
+    /// let mock_message = teloxide_tests::MockMessageChatShared::new();
+    /// let message = mock_message.build();
+    /// assert_eq!(message.shared_chat().unwrap().chat_id.0, teloxide_tests::MockMessageChatShared::SHARED_CHAT_ID);  // SHARED_CHAT_ID is a default value
+    /// ```
+    ///
+    pub fn build(self) -> Message {
+        self.clone()
+            .build_message(MessageKind::ChatShared(MessageChatShared {
+                chat_shared: ChatShared {
+                    request_id: self.request_id,
+                    chat_id: self.shared_chat_id,
+                    title: self.title,
+                    username: self.username,
+                    photo: None,
+                },
+            }))
+    }
+}
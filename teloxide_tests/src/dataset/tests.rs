@@ -1,6 +1,6 @@
 use teloxide::{
     dispatching::dialogue::GetChatId,
-    types::{ChatId, MessageEntity, MessageId, UpdateId, UpdateKind, UserId},
+    types::{ChatId, MessageEntity, MessageId, RequestId, UpdateId, UpdateKind, UserId},
 };
 use update::MockUpdatePoll;
 
@@ -389,6 +389,32 @@ fn test_message_common_migration_from_chat() {
     );
 }
 
+#[test]
+fn test_message_users_shared() {
+    let message = MockMessageUsersShared::new().request_id(RequestId(2));
+
+    let message_object = message.build();
+    let users_shared = message_object.shared_users().unwrap();
+    assert_eq!(users_shared.request_id, RequestId(2));
+    assert_eq!(
+        users_shared.users[0].user_id,
+        UserId(MockMessageUsersShared::SHARED_USER_ID)
+    );
+}
+
+#[test]
+fn test_message_chat_shared() {
+    let message = MockMessageChatShared::new().username("shared");
+
+    let message_object = message.build();
+    let chat_shared = message_object.shared_chat().unwrap();
+    assert_eq!(
+        chat_shared.chat_id,
+        ChatId(MockMessageChatShared::SHARED_CHAT_ID)
+    );
+    assert_eq!(chat_shared.username, Some("shared".to_string()));
+}
+
 //
 //
 //
@@ -91,11 +91,13 @@
 //! - /SetMessageReaction
 //! - /SetMyCommands
 //! - /GetMe
+//! - /SetWebhook
+//! - /DeleteWebhook
+//! - /GetWebhookInfo
+//! - /GetUpdates (see [`MockBot::dispatch_polling`](crate::MockBot::dispatch_polling))
 //!
 //! More endpoints will be added as time goes on!
 //!
-//! (/GetUpdates and /GetWebhookInfo exist, but they are dummies)
-//!
 //! And also fake file downloading!
 //!
 //! ## Why even use unit tests?
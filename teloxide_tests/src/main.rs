@@ -0,0 +1,23 @@
+//! Runs the fake telegram bot API server by itself, so that any bot can be pointed at it.
+//! Read more in [`run_standalone`](teloxide_tests::server::run_standalone).
+use std::env;
+
+use teloxide_tests::{server::run_standalone, MockMe};
+
+const DEFAULT_PORT: u16 = 8081;
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    dotenv::dotenv().ok();
+    pretty_env_logger::init();
+
+    let port = match env::var("TELOXIDE_TESTS_PORT") {
+        Ok(port) => port
+            .parse()
+            .expect("TELOXIDE_TESTS_PORT is not a valid port"),
+        Err(_) => DEFAULT_PORT,
+    };
+
+    log::info!("Starting the fake server on http://127.0.0.1:{port}");
+    run_standalone(("127.0.0.1", port), MockMe::new().build()).await
+}
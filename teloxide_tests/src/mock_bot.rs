@@ -6,10 +6,14 @@ use std::{
     mem::discriminant,
     panic,
     sync::{atomic::AtomicI32, Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
 };
 
+use chrono::{DateTime, Utc};
 use gag::Gag;
 use lazy_static::lazy_static;
+#[cfg(feature = "webhooks")]
+use teloxide::update_listeners::webhooks;
 use teloxide::{
     dispatching::{
         dialogue::{ErasedStorage, GetChatId, InMemStorage, Storage},
@@ -17,16 +21,29 @@ use teloxide::{
     },
     error_handlers::ErrorHandler,
     prelude::*,
-    types::{MaybeInaccessibleMessage, Me, UpdateKind},
+    types::{
+        ButtonRequest, Chat, ChatKind, ChatMemberKind, ChatPermissions, ForceReply,
+        InlineKeyboardButton, InlineKeyboardButtonKind, KeyboardButtonPollType, KeyboardMarkup, Me,
+        PollType, User,
+    },
+    ApiError,
 };
 
+#[cfg(feature = "webhooks")]
+use crate::server::{webhook::deliver_update, DeliveredUpdate};
 // Needed for trait bound stuff
 pub use crate::utils::DistributionKey;
 use crate::{
-    dataset::{IntoUpdate, MockMe},
+    dataset::{
+        IntoUpdate, MockCallbackQuery, MockMe, MockMessageChatShared, MockMessageContact,
+        MockMessageLocation, MockMessagePoll, MockMessageText, MockMessageUsersShared, MockUser,
+    },
     listener::InsertingListener,
     server,
-    server::ServerManager,
+    server::{
+        faults::Fault, flood_control::FloodControl, normalize_method, stubs::StubState,
+        ServerManager,
+    },
     state::State,
     utils::{assert_eqn, default_distribution_function, find_chat_id},
 };
@@ -36,6 +53,8 @@ lazy_static! {
 }
 
 const DEFAULT_STACK_SIZE: usize = 8 * 1024 * 1024;
+const DEFAULT_IDLE_TIME: Duration = Duration::from_millis(200);
+const DEFAULT_DISPATCH_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// A mocked bot that sends requests to the fake server
 /// Please check the [`new`] function docs and [github examples](https://github.com/LasterAlex/teloxide_tests/tree/master/examples) for more information.
@@ -58,12 +77,37 @@ pub struct MockBot<Err, Key> {
     pub dependencies: DependencyMap,
     /// The stack size of the runtime for running updates
     pub stack_size: usize,
+    /// How long the bot has to stay silent after confirming its updates, to be considered done
+    /// with them. Only used by [`dispatch_polling`], because a real bot never says when it is
+    /// done. The handlers that are still running after that are waited for when the bot is
+    /// stopped
+    ///
+    /// [`dispatch_polling`]: crate::MockBot::dispatch_polling
+    pub idle_time: Duration,
+    /// How long [`dispatch_polling`] waits for the bot at most, and then for its handlers to
+    /// finish after it is stopped. If the bot didn't fetch its updates, is still making requests
+    /// or doesn't stop by then, the dispatch panics
+    ///
+    /// [`dispatch_polling`]: crate::MockBot::dispatch_polling
+    pub dispatch_timeout: Duration,
+    /// Run the handlers of [`dispatch`] right on the runtime of the caller, instead of a new
+    /// runtime on a separate thread. This way `tokio::time::pause()` of the test works for the
+    /// handlers, and so do the thread-local things, like a tracing subscriber set with
+    /// `set_default` in a `#[tokio::test]`. `stack_size` is not used then, and the bot that is
+    /// given to the handlers has no request timeouts, so they don't fire when the clock is paused
+    ///
+    /// [`dispatch`]: crate::MockBot::dispatch
+    pub use_current_runtime: bool,
 
     distribution_f: fn(&Update) -> Option<Key>,
     error_handler: Arc<dyn ErrorHandler<Err> + Send + Sync>,
 
     current_update_id: AtomicI32,
     state: Arc<Mutex<State>>,
+    #[cfg(feature = "webhooks")]
+    webhook_secret_token: Option<String>,
+    /// The fake server, that lives as long as the bot. It is started on the first dispatch
+    server: Option<ServerManager>,
     _bot_lock: Option<MutexGuard<'static, ()>>,
 }
 
@@ -143,11 +187,17 @@ where
             handler_tree,
             dependencies: DependencyMap::new(),
             stack_size: DEFAULT_STACK_SIZE,
+            idle_time: DEFAULT_IDLE_TIME,
+            dispatch_timeout: DEFAULT_DISPATCH_TIMEOUT,
+            use_current_runtime: false,
             error_handler: LoggingErrorHandler::new(),
             distribution_f: default_distribution_function,
             _bot_lock: lock,
             current_update_id,
             state,
+            #[cfg(feature = "webhooks")]
+            webhook_secret_token: None,
+            server: None,
         }
     }
 }
@@ -181,11 +231,17 @@ where
             handler_tree,
             dependencies,
             stack_size,
+            idle_time,
+            dispatch_timeout,
+            use_current_runtime,
             error_handler,
             distribution_f: _,
             _bot_lock,
             current_update_id,
             state,
+            #[cfg(feature = "webhooks")]
+            webhook_secret_token,
+            server,
         } = MockBot::new(update, handler_tree);
 
         Self {
@@ -195,11 +251,17 @@ where
             handler_tree,
             dependencies,
             stack_size,
+            idle_time,
+            dispatch_timeout,
+            use_current_runtime,
             error_handler,
             distribution_f: f,
             _bot_lock,
             current_update_id,
             state,
+            #[cfg(feature = "webhooks")]
+            webhook_secret_token,
+            server,
         }
     }
 
@@ -222,62 +284,276 @@ where
         self.updates = update.into_update(&self.current_update_id);
     }
 
+    /// Makes the next update a press of the inline button with the `text` on the `message`, by
+    /// the user of the private chat, or by the default [`MockUser`] in the other chats. The
+    /// keyboard is taken from the message as the fake server has it now, so the edits of the bot
+    /// are taken into account. Panics if there is no such callback button.
+    ///
+    /// ```no_run
+    /// use teloxide::dispatching::UpdateHandler;
+    /// use teloxide::prelude::*;
+    /// use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+    /// use teloxide_tests::{MockBot, MockMessageText};
+    ///
+    /// fn handler_tree() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ///     dptree::entry()
+    ///         .branch(Update::filter_message().endpoint(|message: Message, bot: Bot| async move {
+    ///             let button = InlineKeyboardButton::callback("Press me", "pressed");
+    ///             bot.send_message(message.chat.id, "Hi!")
+    ///                 .reply_markup(InlineKeyboardMarkup::new([[button]]))
+    ///                 .await?;
+    ///             Ok(())
+    ///         }))
+    ///         .branch(Update::filter_callback_query().endpoint(|query: CallbackQuery, bot: Bot| async move {
+    ///             bot.answer_callback_query(query.id).text(query.data.unwrap()).await?;
+    ///             Ok(())
+    ///         }))
+    /// }
+    ///
+    /// #[tokio::main]  // Change for tokio::test in your implementation
+    /// async fn main() {
+    ///     let mut bot = MockBot::new(MockMessageText::new(), handler_tree());
+    ///     bot.dispatch().await;
+    ///
+    ///     let sent_message = bot.get_responses().sent_messages.pop().unwrap();
+    ///     bot.press_button(&sent_message, "Press me");
+    ///     bot.dispatch().await;
+    ///     let answer = bot.get_responses().answered_callback_queries.pop().unwrap();
+    ///     assert_eq!(answer.text.as_deref(), Some("pressed"));
+    /// }
+    /// ```
+    pub fn press_button(&mut self, message: &Message, text: &str) {
+        self.press_button_as(message, chat_user(&message.chat), text);
+    }
+
+    /// Same as [`MockBot::press_button`], but the button is pressed by the `user`. Useful in the
+    /// groups, where anyone can press the buttons
+    pub fn press_button_as(&mut self, message: &Message, user: User, text: &str) {
+        self.press_found_button(message, user, &format!("{text:?}"), |keyboard| {
+            keyboard.iter().flatten().find(|button| button.text == text)
+        });
+    }
+
+    /// Same as [`MockBot::press_button`], but the button is found by its `row` and `column` in
+    /// the keyboard, starting from 0
+    pub fn press_button_at(&mut self, message: &Message, row: usize, column: usize) {
+        self.press_button_at_as(message, chat_user(&message.chat), row, column);
+    }
+
+    /// Same as [`MockBot::press_button_at`], but the button is pressed by the `user`
+    pub fn press_button_at_as(&mut self, message: &Message, user: User, row: usize, column: usize) {
+        let description = format!("at row {row}, column {column}");
+        self.press_found_button(message, user, &description, |keyboard| {
+            keyboard.get(row)?.get(column)
+        });
+    }
+
+    fn stored_message(&self, message: &Message) -> Message {
+        self.state
+            .lock()
+            .unwrap()
+            .messages
+            .get_message(message.chat.id, message.id.0)
+            .unwrap_or_else(|| panic!("The message {} doesn't exist", message.id))
+    }
+
+    /// Presses the callback button that `find` finds in the inline keyboard of the message, as
+    /// the fake server has it now. The `description` of the button is for the panic if there is
+    /// no such button
+    fn press_found_button(
+        &mut self,
+        message: &Message,
+        from: User,
+        description: &str,
+        find: impl FnOnce(&[Vec<InlineKeyboardButton>]) -> Option<&InlineKeyboardButton>,
+    ) {
+        let message = self.stored_message(message);
+        let button = message
+            .reply_markup()
+            .and_then(|markup| find(&markup.inline_keyboard))
+            .unwrap_or_else(|| {
+                panic!(
+                    "There is no button {description} on the message {}",
+                    message.id
+                )
+            })
+            .clone();
+        let InlineKeyboardButtonKind::CallbackData(data) = button.kind else {
+            panic!("The button {:?} is not a callback button", button.text);
+        };
+        self.update(
+            MockCallbackQuery::new()
+                .data(data)
+                .message(message)
+                .from(from),
+        );
+    }
+
+    /// Makes the next update a tap on the button with the `text` of the reply keyboard that is
+    /// open in the `chat`, by the user of the private chat, or by the default [`MockUser`] in the
+    /// other chats. Panics if the keyboard with such button isn't shown.
+    ///
+    /// The plain buttons send their text. The request buttons send what the user would share
+    /// with them: a contact, a location, a poll, or a users or chat shared service message.
+    ///
+    /// ```no_run
+    /// use teloxide::prelude::*;
+    /// use teloxide::types::{KeyboardButton, KeyboardMarkup};
+    /// use teloxide_tests::{MockBot, MockMessageText};
+    ///
+    /// #[tokio::main]  // Change for tokio::test in your implementation
+    /// async fn main() {
+    ///     let handler = Update::filter_message().endpoint(|message: Message, bot: Bot| async move {
+    ///         let keyboard = KeyboardMarkup::new([[KeyboardButton::new("Yes")]]);
+    ///         let text = format!("You said {}", message.text().unwrap());
+    ///         bot.send_message(message.chat.id, text).reply_markup(keyboard).await?;
+    ///         Ok::<_, teloxide::RequestError>(())
+    ///     });
+    ///     let mut bot = MockBot::new(MockMessageText::new().text("Hi"), handler);
+    ///     bot.dispatch().await;
+    ///
+    ///     let chat = bot.get_responses().sent_messages.pop().unwrap().chat;
+    ///     bot.press_keyboard_button(&chat, "Yes");
+    ///     bot.dispatch_and_check_last_text("You said Yes").await;
+    /// }
+    /// ```
+    pub fn press_keyboard_button(&mut self, chat: &Chat, text: &str) {
+        self.press_keyboard_button_as(chat, chat_user(chat), text);
+    }
+
+    /// Same as [`MockBot::press_keyboard_button`], but the button is tapped by the `user`. Useful
+    /// in the groups, where the keyboards may be shown only to some of the users
+    pub fn press_keyboard_button_as(&mut self, chat: &Chat, user: User, text: &str) {
+        let keyboard = self
+            .state
+            .lock()
+            .unwrap()
+            .reply_keyboards
+            .keyboard(chat.id, user.id);
+        let button = keyboard
+            .as_ref()
+            .and_then(|keyboard| {
+                keyboard
+                    .keyboard
+                    .iter()
+                    .flatten()
+                    .find(|button| button.text == text)
+            })
+            .unwrap_or_else(|| {
+                panic!(
+                    "There is no keyboard button {text:?} shown to the user {} in the chat {}",
+                    user.id, chat.id
+                )
+            });
+
+        let (chat, from) = (chat.clone(), user.clone());
+        let message = match &button.request {
+            None => MockMessageText::new()
+                .text(text)
+                .chat(chat)
+                .from(from)
+                .build(),
+            Some(ButtonRequest::Contact) => {
+                let mut contact = MockMessageContact::new()
+                    .first_name(user.first_name.clone())
+                    .user_id(user.id)
+                    .chat(chat)
+                    .from(from);
+                contact.last_name = user.last_name.clone();
+                contact.build()
+            }
+            Some(ButtonRequest::Location) => {
+                MockMessageLocation::new().chat(chat).from(from).build()
+            }
+            Some(ButtonRequest::Poll(poll_type)) => {
+                let poll_type = match poll_type {
+                    KeyboardButtonPollType::Quiz => PollType::Quiz,
+                    _ => PollType::Regular,
+                };
+                MockMessagePoll::new()
+                    .poll_type(poll_type)
+                    .chat(chat)
+                    .from(from)
+                    .build()
+            }
+            Some(ButtonRequest::RequestUsers(request)) => MockMessageUsersShared::new()
+                .request_id(request.request_id)
+                .chat(chat)
+                .from(from)
+                .build(),
+            Some(ButtonRequest::RequestChat(request)) => MockMessageChatShared::new()
+                .request_id(request.request_id)
+                .chat(chat)
+                .from(from)
+                .build(),
+            Some(ButtonRequest::WebApp(_)) => {
+                panic!("The web app of the button {text:?} can't be opened in the tests")
+            }
+        };
+        self.update(message);
+    }
+
     /// Sets the error_handler for Dispather
     pub fn error_handler(&mut self, handler: Arc<dyn ErrorHandler<Err> + Send + Sync>) {
         self.error_handler = handler;
     }
 
-    /// Just inserts the updates into the state, returning them
-    fn insert_updates(&self, updates: &mut [Update]) {
-        for update in updates.iter_mut() {
-            match update.kind.clone() {
-                UpdateKind::Message(mut message) => {
-                    // Add the message to the list of messages, so the bot can interact with it
-                    self.state.lock().unwrap().add_message(&mut message);
-                    update.kind = UpdateKind::Message(message.clone());
-                }
-                UpdateKind::EditedMessage(mut message) => {
-                    self.state.lock().unwrap().edit_message(&mut message);
-                    update.kind = UpdateKind::EditedMessage(message.clone());
-                }
-                UpdateKind::CallbackQuery(mut callback) => {
-                    if let Some(MaybeInaccessibleMessage::Regular(ref mut message)) =
-                        callback.message
-                    {
-                        self.state.lock().unwrap().add_message(message);
-                    }
-                    update.kind = UpdateKind::CallbackQuery(callback.clone());
-                }
-                _ => {}
-            }
-        }
+    /// Makes the fake server send this secret token with the updates in [`dispatch_webhook`],
+    /// instead of the one the webhook was set with. Useful to check that the webhook doesn't
+    /// accept updates from strangers.
+    ///
+    /// [`dispatch_webhook`]: crate::MockBot::dispatch_webhook
+    #[cfg(feature = "webhooks")]
+    pub fn webhook_secret_token<S: Into<String>>(&mut self, secret_token: S) {
+        self.webhook_secret_token = Some(secret_token.into());
     }
 
-    async fn run_updates(&self, bot: Bot, updates: Vec<Update>) {
+    /// Just inserts the updates into the state, dropping the ones that the users can't send
+    fn insert_updates(&self, updates: &mut Vec<Update>) {
+        let mut lock = self.state.lock().unwrap();
+        updates.retain_mut(|update| lock.insert_update(update));
+    }
+
+    async fn run_updates(&self, mut bot: Bot, updates: Vec<Update>) {
+        if self.use_current_runtime {
+            // The clock of the caller can be paused, and then it jumps forward while the bot
+            // waits for the fake server, so the timeouts of the default client would fire. The
+            // idle connection timer of the pool mixes the real and the paused clocks, and would
+            // wake up the runtime forever
+            let client = reqwest::Client::builder()
+                .pool_idle_timeout(None)
+                .build()
+                .unwrap();
+            bot = Bot::with_client(bot.token(), client).set_api_url(bot.api_url());
+        }
         let handler_tree = self.handler_tree.clone();
         let deps = self.dependencies.clone();
         let stack_size = self.stack_size;
         let distribution_f = self.distribution_f.clone();
         let error_handler = self.error_handler.clone();
 
+        let dispatch = async move {
+            Dispatcher::builder(bot.clone(), handler_tree.clone())
+                .dependencies(deps)
+                .distribution_function(distribution_f)
+                .error_handler(error_handler)
+                .build()
+                .dispatch_with_listener(InsertingListener { updates }, LoggingErrorHandler::new())
+                .await;
+        };
+
+        if self.use_current_runtime {
+            dispatch.await;
+            return;
+        }
+
         tokio::task::spawn_blocking(move || {
             let runtime = tokio::runtime::Builder::new_multi_thread()
                 .thread_stack_size(stack_size) // Not needed, but just in case
                 .enable_all()
                 .build()
                 .unwrap();
-            runtime.block_on(async {
-                Dispatcher::builder(bot.clone(), handler_tree.clone())
-                    .dependencies(deps)
-                    .distribution_function(distribution_f)
-                    .error_handler(error_handler)
-                    .build()
-                    .dispatch_with_listener(
-                        InsertingListener { updates },
-                        LoggingErrorHandler::new(),
-                    )
-                    .await;
-            });
+            runtime.block_on(dispatch);
         })
         .await
         .expect("Dispatcher panicked!");
@@ -289,26 +565,497 @@ where
     /// every new dispatch.
     ///
     /// This method overrides env variables `TELOXIDE_TOKEN` and `TELOXIDE_API_URL`, so anyone can
-    /// call `Bot::from_env()` and get an actual bot that is connected to the fake server
+    /// call `Bot::from_env()` and get an actual bot that is connected to the fake server. The fake
+    /// server is started on the first dispatch and lives as long as the `MockBot`, so the api url
+    /// doesn't change between the dispatches (unless `me` is changed)
     pub async fn dispatch(&mut self) {
         self.state.lock().unwrap().reset();
 
-        let server = ServerManager::start(self.me.clone(), self.state.clone())
-            .await
-            .unwrap();
+        let bot = self.connect_to_server();
+
+        let mut updates = self.updates.clone();
+        self.insert_updates(&mut updates);
+
+        self.run_updates(bot, updates).await;
+        self.resume_stub_panic();
+    }
+
+    /// Raises the panic of a stub (like a failed assert in it) that the fake server caught, so
+    /// the test fails with it
+    fn resume_stub_panic(&self) {
+        let stub_panic = self.state.lock().unwrap().stub_panic.take();
+        if let Some(stub_panic) = stub_panic {
+            panic::resume_unwind(stub_panic);
+        }
+    }
+
+    /// Dispatches the updates through the fake `getUpdates` endpoint, so that an unmodified bot,
+    /// that uses `Bot::from_env()` and a regular `Dispatcher::dispatch()` (which uses
+    /// teloxide's own `Polling` listener), can be tested. The handler tree of the mocked bot is
+    /// not used here, the dispatcher from `make_dispatcher` uses its own.
+    ///
+    /// The updates are put in the update queue of the fake server, and the dispatcher is run
+    /// until all of them are confirmed by the bot, and the bot stops making requests for
+    /// [`idle_time`]. After that the dispatcher is stopped with its shutdown token, which waits
+    /// for the handlers that are still running and confirms the last updates, and the responses
+    /// can be checked as usual.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use teloxide::dispatching::{DefaultKey, UpdateHandler};
+    /// use teloxide::prelude::*;
+    /// use teloxide_tests::{MockBot, MockMessageText};
+    ///
+    /// type HandlerResult = Box<dyn std::error::Error + Send + Sync + 'static>;
+    ///
+    /// fn handler_tree() -> UpdateHandler<HandlerResult> {
+    ///     Update::filter_message().endpoint(|message: Message, bot: Bot| async move {
+    ///         bot.send_message(message.chat.id, "Hello!").await?;
+    ///         Ok(())
+    ///     })
+    /// }
+    ///
+    /// // What your main function does, before calling `dispatch()`
+    /// fn dispatcher() -> Dispatcher<Bot, HandlerResult, DefaultKey> {
+    ///     let bot = Bot::from_env();
+    ///     Dispatcher::builder(bot, handler_tree()).build()
+    /// }
+    ///
+    /// #[tokio::main]  // Change for tokio::test in your implementation
+    /// async fn main() {
+    ///     let mut bot = MockBot::new(MockMessageText::new().text("Hi!"), handler_tree());
+    ///     bot.dispatch_polling(dispatcher).await;
+    ///     let responses = bot.get_responses();
+    ///     assert_eq!(responses.sent_messages.last().unwrap().text(), Some("Hello!"));
+    /// }
+    /// ```
+    ///
+    /// [`idle_time`]: crate::MockBot::idle_time
+    pub async fn dispatch_polling<R, E, K, F>(&mut self, make_dispatcher: F)
+    where
+        R: Requester + Clone + Send + Sync + 'static,
+        <R as Requester>::GetUpdates: Send,
+        E: Send + Sync + 'static,
+        K: Hash + Eq + Clone + Send,
+        F: FnOnce() -> Dispatcher<R, E, K> + Send + 'static,
+    {
+        self.state.lock().unwrap().reset();
+
+        // The bot under test makes its own bot with `Bot::from_env()`
+        let _ = self.connect_to_server();
+
+        let mut updates = self.updates.clone();
+        self.insert_updates(&mut updates);
+        {
+            let mut lock = self.state.lock().unwrap();
+            for update in updates {
+                lock.update_queue.push(update);
+            }
+            // The bot gets `idle_time` to start working even if it has no updates
+            lock.last_request_at = Some(Instant::now());
+        }
+
+        let state = self.state.clone();
+        let stack_size = self.stack_size;
+        let idle_time = self.idle_time;
+        let timeout = self.dispatch_timeout;
+        let result = tokio::task::spawn_blocking(move || {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .thread_stack_size(stack_size)
+                .enable_all()
+                .build()
+                .unwrap();
+            let result = runtime.block_on(async {
+                let mut dispatcher = make_dispatcher();
+                let shutdown_token = dispatcher.shutdown_token();
+                let dispatching = dispatcher.dispatch();
+                tokio::pin!(dispatching);
+                let result = tokio::select! {
+                    _ = &mut dispatching => {
+                        log::debug!("The bot stopped by itself");
+                        return Ok(());
+                    }
+                    result = wait_until_idle(state, idle_time, timeout) => result,
+                };
+                // The dispatcher waits for its handlers and confirms the last updates before it
+                // stops. It can't be stopped if it didn't even start dispatching
+                if shutdown_token.shutdown().is_ok()
+                    && tokio::time::timeout(timeout, dispatching).await.is_err()
+                {
+                    return result.and(Err(format!(
+                        "The bot didn't finish handling its updates in {timeout:?} after it was \
+                         stopped"
+                    )));
+                }
+                result
+            });
+            // Whatever the bot left running if it couldn't be stopped is just dropped
+            runtime.shutdown_background();
+            result
+        })
+        .await
+        .expect("Bot panicked!");
+        self.resume_stub_panic();
+        if let Err(message) = result {
+            panic!("{message}");
+        }
+    }
+
+    /// Dispatches the updates through teloxide's axum webhook listener, made with `options`,
+    /// instead of feeding them straight to the dispatcher. The listener binds to
+    /// `options.address` and sets its webhook on the fake server, and the fake server sends
+    /// every update to it with a POST request, just like telegram does, including the
+    /// `X-Telegram-Bot-Api-Secret-Token` header.
+    ///
+    /// The status codes that the webhook responded with can be found in
+    /// `responses.delivered_updates`. Needs the `webhooks` feature.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use teloxide::dispatching::UpdateHandler;
+    /// use teloxide::prelude::*;
+    /// use teloxide::update_listeners::webhooks;
+    /// use teloxide_tests::{MockBot, MockMessageText};
+    ///
+    /// fn handler_tree() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ///     Update::filter_message().endpoint(|message: Message, bot: Bot| async move {
+    ///         bot.send_message(message.chat.id, "Hello!").await?;
+    ///         Ok(())
+    ///     })
+    /// }
+    ///
+    /// #[tokio::main]  // Change for tokio::test in your implementation
+    /// async fn main() {
+    ///     let mut bot = MockBot::new(MockMessageText::new().text("Hi!"), handler_tree());
+    ///     let address = ([127, 0, 0, 1], 8443).into();
+    ///     let url = "http://127.0.0.1:8443/webhook".parse().unwrap();
+    ///     let options = webhooks::Options::new(address, url).secret_token("secret".into());
+    ///
+    ///     bot.dispatch_webhook(options).await;
+    ///
+    ///     let responses = bot.get_responses();
+    ///     assert_eq!(responses.delivered_updates[0].status, 200);
+    ///     assert_eq!(responses.sent_messages.last().unwrap().text(), Some("Hello!"));
+    /// }
+    /// ```
+    #[cfg(feature = "webhooks")]
+    pub async fn dispatch_webhook(&mut self, options: webhooks::Options) {
+        self.state.lock().unwrap().reset();
+
+        let bot = self.connect_to_server();
 
         let mut updates = self.updates.clone();
         self.insert_updates(&mut updates);
 
-        let api_url = reqwest::Url::parse(&format!("http://127.0.0.1:{}", server.port)).unwrap();
+        let handler_tree = self.handler_tree.clone();
+        let deps = self.dependencies.clone();
+        let stack_size = self.stack_size;
+        let distribution_f = self.distribution_f.clone();
+        let error_handler = self.error_handler.clone();
+        let state = self.state.clone();
+        let secret_token = self.webhook_secret_token.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .thread_stack_size(stack_size)
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(async {
+                let listener = webhooks::axum(bot.clone(), options)
+                    .await
+                    .expect("Couldn't set up the webhook");
+                let mut dispatcher = Dispatcher::builder(bot, handler_tree)
+                    .dependencies(deps)
+                    .distribution_function(distribution_f)
+                    .error_handler(error_handler)
+                    .build();
+                let shutdown_token = dispatcher.shutdown_token();
+
+                let deliver_updates = async move {
+                    let webhook = state
+                        .lock()
+                        .unwrap()
+                        .webhook
+                        .clone()
+                        .expect("The webhook wasn't set!");
+                    let secret_token = secret_token.or(webhook.secret_token.clone());
+                    let client = reqwest::Client::new();
+                    for update in updates {
+                        let status =
+                            deliver_update(&client, &webhook, secret_token.as_deref(), &update)
+                                .await
+                                .expect("Couldn't send the update to the webhook");
+                        state
+                            .lock()
+                            .unwrap()
+                            .responses
+                            .delivered_updates
+                            .push(DeliveredUpdate { update, status });
+                    }
+
+                    // The dispatcher finishes all of the received updates before shutting down
+                    loop {
+                        match shutdown_token.shutdown() {
+                            Ok(shutdown) => break shutdown.await,
+                            // The dispatcher didn't start yet
+                            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+                        }
+                    }
+                };
+
+                tokio::join!(
+                    dispatcher.dispatch_with_listener(listener, LoggingErrorHandler::new()),
+                    deliver_updates
+                );
+            });
+        })
+        .await
+        .expect("Dispatcher panicked!");
+        self.resume_stub_panic();
+    }
+
+    /// Starts the fake server if it isn't running yet, and points the bot and the env variables to
+    /// it. The server is reused by every dispatch, so the api url stays the same for the whole
+    /// life of the bot
+    fn connect_to_server(&mut self) -> Bot {
+        if self
+            .server
+            .as_ref()
+            .map_or(true, |server| server.me != self.me)
+        {
+            // `me` is given to the server on start, so the server has to be restarted if it changed
+            self.server = None;
+            self.server = Some(ServerManager::start(self.me.clone(), self.state.clone()).unwrap());
+        }
+        let port = self.server.as_ref().unwrap().port;
+
+        let api_url = reqwest::Url::parse(&format!("http://127.0.0.1:{port}")).unwrap();
         let bot = self.bot.clone().set_api_url(api_url.clone());
 
         env::set_var("TELOXIDE_TOKEN", bot.token());
         env::set_var("TELOXIDE_API_URL", api_url.to_string());
 
-        self.run_updates(bot, updates).await;
+        bot
+    }
+
+    /// Moves the clock of the fake server forward by `duration`, without waiting. After that the
+    /// messages have later dates, bans and restrictions with `until_date` run out, polls with
+    /// `open_period` or `close_date` get closed, and the messages older than 48 hours can't be
+    /// deleted anymore. The dates of the dispatched updates are moved forward as well, including
+    /// the dates set on the mocks by hand and the `edit_date` of the edited messages, so they stay
+    /// relative to the time of the fake server. After [`MockBot::set_time`] the updates are
+    /// dated at the time of the clock instead.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use teloxide::dispatching::UpdateHandler;
+    /// use teloxide::prelude::*;
+    /// use teloxide_tests::{MockBot, MockMessageText};
+    ///
+    /// fn handler_tree() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ///     Update::filter_message().endpoint(|message: Message, bot: Bot| async move {
+    ///         bot.delete_message(message.chat.id, message.id).await?;
+    ///         Ok(())
+    ///     })
+    /// }
+    ///
+    /// #[tokio::main]  // Change for tokio::test in your implementation
+    /// async fn main() {
+    ///     let mut bot = MockBot::new(MockMessageText::new(), handler_tree());
+    ///     bot.advance_time(Duration::from_secs(60 * 60 * 24 * 7));
+    ///     bot.dispatch().await;
+    ///     // The update was sent right now, so it can still be deleted
+    ///     assert_eq!(bot.get_responses().deleted_messages.len(), 1);
+    /// }
+    /// ```
+    pub fn advance_time(&self, duration: Duration) {
+        self.state.lock().unwrap().advance_time(duration);
+    }
+
+    /// Stops the clock of the fake server at `time`. After that it only moves with
+    /// [`MockBot::advance_time`], so the dates in the responses are the same between the runs.
+    /// The dispatched updates are dated at the time of the clock as well, whatever the dates of
+    /// the mocks are
+    pub fn set_time(&self, time: DateTime<Utc>) {
+        self.state.lock().unwrap().clock.set(time);
+    }
+
+    /// Seeds the random generator of the fake server, so that file ids, media group ids and dice
+    /// values are the same between the runs
+    pub fn seed(&self, seed: u64) {
+        self.state.lock().unwrap().random.seed(seed);
+    }
+
+    /// Makes the fake server answer the `method` with the `handler`, instead of whatever it did
+    /// before. It works for the endpoints that aren't implemented yet, as well as for the built-in
+    /// ones, if you need them to return something specific. The handler gets the body of the
+    /// request as JSON (multipart requests are turned into it too), and its `Ok` value is put in
+    /// the `result` of the response. The stub stays for the whole life of the bot.
+    ///
+    /// The handler can check the request with asserts. If it panics, the bot gets a server error,
+    /// and the panic is raised again once the dispatch is over, failing the test.
+    ///
+    /// ```no_run
+    /// use teloxide::dispatching::UpdateHandler;
+    /// use teloxide::prelude::*;
+    /// use teloxide::types::MenuButton;
+    /// use teloxide_tests::{MockBot, MockMessageText};
+    ///
+    /// fn handler_tree() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ///     Update::filter_message().endpoint(|message: Message, bot: Bot| async move {
+    ///         let button = bot.get_chat_menu_button().chat_id(message.chat.id).await?;
+    ///         bot.send_message(message.chat.id, format!("{button:?}")).await?;
+    ///         Ok(())
+    ///     })
+    /// }
+    ///
+    /// #[tokio::main]  // Change for tokio::test in your implementation
+    /// async fn main() {
+    ///     let mut bot = MockBot::new(MockMessageText::new(), handler_tree());
+    ///     bot.stub_endpoint("GetChatMenuButton", |_request, _state| {
+    ///         Ok(serde_json::to_value(MenuButton::Commands).unwrap())
+    ///     });
+    ///     bot.dispatch_and_check_last_text("Commands").await;
+    /// }
+    /// ```
+    pub fn stub_endpoint<F>(&self, method: &str, handler: F)
+    where
+        F: Fn(serde_json::Value, &mut StubState) -> Result<serde_json::Value, ApiError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.state
+            .lock()
+            .unwrap()
+            .stubs
+            .insert(normalize_method(method), Box::new(handler));
+    }
 
-        server.stop().await.unwrap();
+    /// Makes the fake server fail the calls that the `fault` describes, to test how the bot
+    /// handles errors. The fault is removed after it failed all of its calls, and until then it
+    /// stays between the dispatches.
+    ///
+    /// ```no_run
+    /// use teloxide::dispatching::UpdateHandler;
+    /// use teloxide::prelude::*;
+    /// use teloxide::ApiError;
+    /// use teloxide_tests::server::faults::{Fault, FaultKind};
+    /// use teloxide_tests::{MockBot, MockMessageText};
+    ///
+    /// fn handler_tree() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+    ///     Update::filter_message().endpoint(|message: Message, bot: Bot| async move {
+    ///         if bot.send_message(message.chat.id, "Hi!").await.is_err() {
+    ///             bot.send_message(message.chat.id, "Couldn't say hi").await?;
+    ///         }
+    ///         Ok(())
+    ///     })
+    /// }
+    ///
+    /// #[tokio::main]  // Change for tokio::test in your implementation
+    /// async fn main() {
+    ///     let mut bot = MockBot::new(MockMessageText::new(), handler_tree());
+    ///     bot.inject_fault(Fault::new("SendMessage", FaultKind::Api(ApiError::BotBlocked)));
+    ///     bot.dispatch_and_check_last_text("Couldn't say hi").await;
+    /// }
+    /// ```
+    pub fn inject_fault(&self, fault: Fault) {
+        self.state.lock().unwrap().faults.push(fault);
+    }
+
+    /// Makes the fake server limit the messages the way telegram does: about one message per
+    /// second in a chat, 20 messages per minute in a group, and 30 messages per second overall.
+    /// Messages over the limit get `RequestError::RetryAfter`, and are recorded in
+    /// `throttled_requests` of the responses. The limits go by the time of the fake server, so
+    /// [`MockBot::advance_time`] can be used to wait them out.
+    pub fn enable_flood_control(&self) {
+        self.state.lock().unwrap().flood_control = Some(FloodControl::default());
+    }
+
+    /// Makes the fake server know only the chats and the users that appeared in the updates, or
+    /// were added with [`MockBot::add_chat`] and [`MockBot::add_user`]. The requests about
+    /// anyone else get `ApiError::ChatNotFound` or `ApiError::UserNotFound`, like they would if
+    /// the bot sent them to a wrong id.
+    pub fn enable_strict_mode(&self) {
+        self.state.lock().unwrap().chats.strict = true;
+    }
+
+    /// Makes the chat known to the fake server before it appears in the updates. The messages
+    /// the bot sends there get this chat, instead of the one guessed from the sign of its id
+    pub fn add_chat(&self, chat: Chat) {
+        let mut lock = self.state.lock().unwrap();
+        lock.usernames.add_chat(&chat);
+        lock.chats.add_chat(chat);
+    }
+
+    /// Makes the user known to the fake server before they appear in the updates
+    pub fn add_user(&self, user: User) {
+        self.state.lock().unwrap().chats.add_user(user);
+    }
+
+    /// Lets the bot use `@username` instead of the id of the chat. The channels and the
+    /// supergroups in the updates are added by themselves, if they have a username. Unknown
+    /// usernames get `ApiError::ChatNotFound`.
+    pub fn add_chat_username(&self, username: &str, chat_id: ChatId) {
+        self.state.lock().unwrap().usernames.add(username, chat_id);
+    }
+
+    /// Returns the current time of the fake server
+    pub fn now(&self) -> DateTime<Utc> {
+        self.state.lock().unwrap().clock.now()
+    }
+
+    /// Checks if the user is banned in the chat by the bot, and the ban didn't run out yet
+    pub fn is_banned(&self, chat_id: ChatId, user_id: UserId) -> bool {
+        self.state.lock().unwrap().is_banned(chat_id, user_id)
+    }
+
+    /// Sets what the bot is in the chat, so the fake server checks its rights. Pinning,
+    /// restricting, deleting the messages of the others and posting to a channel without the
+    /// rights for it get the errors telegram would give, like `ApiError::NotEnoughRightsToRestrict`.
+    /// Without it, the bot can do anything in the chat.
+    pub fn set_bot_member(&self, chat_id: ChatId, kind: ChatMemberKind) {
+        self.state
+            .lock()
+            .unwrap()
+            .permissions
+            .set_bot_member(chat_id, kind);
+    }
+
+    /// Returns the permissions of the user in the chat, if the bot restricted them, and the
+    /// restriction didn't run out yet. The messages they aren't allowed to send, and all the
+    /// messages of the banned users, are not dispatched
+    pub fn restrictions(&self, chat_id: ChatId, user_id: UserId) -> Option<ChatPermissions> {
+        self.state.lock().unwrap().restrictions(chat_id, user_id)
+    }
+
+    /// Returns the reply keyboard that the user sees in the chat, if the bot has shown one and
+    /// didn't remove it yet. A one time keyboard hides after the user presses its button
+    pub fn reply_keyboard(&self, chat_id: ChatId, user_id: UserId) -> Option<KeyboardMarkup> {
+        self.state
+            .lock()
+            .unwrap()
+            .reply_keyboards
+            .keyboard(chat_id, user_id)
+    }
+
+    /// Returns the force reply that waits for the user to answer the bot in the chat
+    pub fn force_reply(&self, chat_id: ChatId, user_id: UserId) -> Option<ForceReply> {
+        self.state
+            .lock()
+            .unwrap()
+            .reply_keyboards
+            .force_reply(chat_id, user_id)
+    }
+
+    /// Returns the messages of the chat as they are now, both the bot's and the users', in the
+    /// order they were sent. Unlike `sent_messages` in the responses, the edits are applied and
+    /// the deleted messages are gone
+    pub fn chat_history(&self, chat_id: ChatId) -> Vec<Message> {
+        self.state.lock().unwrap().messages.chat_history(chat_id)
     }
 
     /// Returns the responses stored in `responses`
@@ -623,3 +1370,52 @@ where
         }
     }
 }
+
+/// Waits until the bot fetched and confirmed all of the queued updates, and stopped making
+/// requests. Returns the error to panic with if that doesn't happen in `timeout`
+async fn wait_until_idle(
+    state: Arc<Mutex<State>>,
+    idle_time: Duration,
+    timeout: Duration,
+) -> Result<(), String> {
+    let started_at = Instant::now();
+    loop {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let lock = state.lock().unwrap();
+        let is_quiet = lock
+            .last_request_at
+            .map_or(true, |last_request| last_request.elapsed() >= idle_time);
+        let queue = &lock.update_queue;
+        if queue.is_empty() && is_quiet {
+            return Ok(());
+        }
+        if started_at.elapsed() < timeout {
+            continue;
+        }
+        if !queue.is_empty() {
+            return Err(format!(
+                "The bot didn't fetch {} of its updates in {timeout:?}. Does it poll the fake \
+                 server with `Bot::from_env()`?",
+                queue.len()
+            ));
+        }
+        return Err(format!(
+            "The bot was still making requests after {timeout:?}"
+        ));
+    }
+}
+
+/// The user that acts in the `chat`. In a private chat it can only be the user of the chat
+fn chat_user(chat: &Chat) -> User {
+    let mut user = MockUser::new().build();
+    if let ChatKind::Private(private) = &chat.kind {
+        user.id = UserId(chat.id.0 as u64);
+        // The fake server doesn't always know the names of the user
+        if let Some(first_name) = &private.first_name {
+            user.first_name = first_name.clone();
+            user.last_name = private.last_name.clone();
+        }
+        user.username = private.username.clone().or(user.username);
+    }
+    user
+}
@@ -0,0 +1,88 @@
+//! Endpoints that are not a part of the bot API, used to control the fake server when it runs by
+//! itself (see [`run_standalone`](crate::server::run_standalone))
+use std::sync::Mutex;
+
+use actix_web::{error::ErrorBadRequest, web, HttpResponse, Responder};
+use serde_json::Value;
+use teloxide::types::Update;
+
+use super::{webhook::deliver_update, DeliveredUpdate};
+use crate::state::State;
+
+/// Takes an update or a list of updates, in the same format telegram sends them, and gives them
+/// to the bot: sends them to the webhook if it is set, otherwise puts them in the `getUpdates`
+/// queue. Responds with the updates as they were inserted (the message ids may change).
+pub async fn add_updates(body: web::Json<Value>, state: web::Data<Mutex<State>>) -> HttpResponse {
+    // `Update` can't be deserialized from a `Value` (it silently becomes `UpdateKind::Error`), so
+    // every update is parsed from its string
+    let updates = match body.into_inner() {
+        Value::Array(updates) => updates
+            .into_iter()
+            .map(|update| serde_json::from_str(&update.to_string()))
+            .collect::<Result<Vec<Update>, _>>(),
+        update => serde_json::from_str(&update.to_string()).map(|update| vec![update]),
+    };
+    let mut updates = match updates {
+        Ok(updates) => updates,
+        Err(err) => return ErrorBadRequest(format!("Invalid update: {err}")).into(),
+    };
+
+    let webhook = {
+        let mut lock = state.lock().unwrap();
+        updates.retain_mut(|update| lock.insert_update(update));
+        match lock.webhook.clone() {
+            Some(webhook) => webhook,
+            None => {
+                for update in updates.iter() {
+                    lock.update_queue.push(update.clone());
+                }
+                return HttpResponse::Ok().json(updates);
+            }
+        }
+    };
+
+    let client = reqwest::Client::new();
+    for update in updates.iter() {
+        let status = match deliver_update(
+            &client,
+            &webhook,
+            webhook.secret_token.as_deref(),
+            update,
+        )
+        .await
+        {
+            Ok(status) => status,
+            Err(err) => {
+                return HttpResponse::BadGateway()
+                    .body(format!("Couldn't send the update to the webhook: {err}"))
+            }
+        };
+        state
+            .lock()
+            .unwrap()
+            .responses
+            .delivered_updates
+            .push(DeliveredUpdate {
+                update: update.clone(),
+                status,
+            });
+    }
+    HttpResponse::Ok().json(updates)
+}
+
+/// Returns everything that is stored in `Responses`
+pub async fn get_responses(state: web::Data<Mutex<State>>) -> impl Responder {
+    HttpResponse::Ok().json(&state.lock().unwrap().responses)
+}
+
+/// Returns all of the messages the fake server knows about, with edits applied
+pub async fn get_messages(state: web::Data<Mutex<State>>) -> impl Responder {
+    let state = state.lock().unwrap();
+    HttpResponse::Ok().json(state.messages.iter().collect::<Vec<_>>())
+}
+
+/// Clears the responses, like a new dispatch of `MockBot` does
+pub async fn reset(state: web::Data<Mutex<State>>) -> impl Responder {
+    state.lock().unwrap().reset();
+    HttpResponse::Ok().finish()
+}
@@ -0,0 +1,170 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use actix_web::{
+    body::BoxBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::ResponseError,
+    middleware::Next,
+    web::Data,
+};
+use serde_json::Value;
+use teloxide::{
+    types::{Chat, ChatId, Update, User, UserId},
+    ApiError,
+};
+
+use super::{
+    request_body,
+    routes::{BodyChatId, BotApiError},
+};
+use crate::state::State;
+
+/// The fields of the requests with the chat ids in them. They can have `@username` instead of the
+/// chat id as well
+pub(super) const CHAT_ID_FIELDS: [&str; 2] = ["chat_id", "from_chat_id"];
+
+/// The chats and the users the fake server knows about: the ones from the updates, and the ones
+/// added with the `MockBot`
+#[derive(Debug, Default)]
+pub struct Chats {
+    chats: HashMap<ChatId, Chat>,
+    users: HashMap<UserId, User>,
+    /// If the bot can only use the known chats and users. Off by default, so any chat id works
+    pub(crate) strict: bool,
+}
+
+impl Chats {
+    /// Adds the chat, or updates it if it is already known
+    pub fn add_chat(&mut self, chat: Chat) {
+        self.chats.insert(chat.id, chat);
+    }
+
+    /// Adds the user, or updates them if they are already known
+    pub fn add_user(&mut self, user: User) {
+        self.users.insert(user.id, user);
+    }
+
+    pub fn get_chat(&self, chat_id: ChatId) -> Option<&Chat> {
+        self.chats.get(&chat_id)
+    }
+
+    pub fn get_user(&self, user_id: UserId) -> Option<&User> {
+        self.users.get(&user_id)
+    }
+
+    /// The known user with the `username` (with or without the `@`). Usernames don't care about
+    /// the case
+    pub(crate) fn find_user(&self, username: &str) -> Option<&User> {
+        let username = username.trim_start_matches('@');
+        self.users.values().find(|user| {
+            user.username
+                .as_deref()
+                .is_some_and(|name| name.eq_ignore_ascii_case(username))
+        })
+    }
+
+    /// Remembers the chat of the update, and every user in it
+    pub(crate) fn add_update(&mut self, update: &Update) {
+        if let Some(chat) = update.chat() {
+            self.add_chat(chat.clone());
+        }
+        for user in update.mentioned_users() {
+            self.add_user(user.clone());
+        }
+    }
+
+    /// The chat the bot sends to. If the chat isn't known, it is guessed from the sign of the id
+    pub(crate) fn chat(&self, chat_id: &BodyChatId) -> Chat {
+        self.get_chat(ChatId(chat_id.id()))
+            .cloned()
+            .unwrap_or_else(|| chat_id.chat())
+    }
+
+    /// In the strict mode, returns the error for the chats and the users of the request that
+    /// aren't known
+    fn check(&self, body: &Value) -> Result<(), ApiError> {
+        if !self.strict {
+            return Ok(());
+        }
+        for field in CHAT_ID_FIELDS {
+            let Some(chat_id) = as_i64(&body[field]) else {
+                continue;
+            };
+            if self.get_chat(ChatId(chat_id)).is_none() {
+                return Err(ApiError::ChatNotFound);
+            }
+        }
+        if let Some(user_id) = as_i64(&body["user_id"]) {
+            if self.get_user(UserId(user_id as u64)).is_none() {
+                return Err(ApiError::UserNotFound);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The ids can come as numbers, or as strings in the multipart requests
+pub(super) fn as_i64(value: &Value) -> Option<i64> {
+    value.as_i64().or_else(|| value.as_str()?.parse().ok())
+}
+
+/// Responds with `ChatNotFound` or `UserNotFound` if the strict mode is on, and the request is
+/// about a chat or a user the fake server doesn't know
+pub(crate) async fn check_request(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let (Some(state), Some(body)) = (
+        req.app_data::<Data<Mutex<State>>>().cloned(),
+        request_body(&req),
+    ) else {
+        return next.call(req).await;
+    };
+    if !state.lock().unwrap().chats.strict {
+        return next.call(req).await;
+    }
+
+    let checked = state.lock().unwrap().chats.check(&body.json);
+    match checked {
+        Ok(()) => next.call(req).await,
+        Err(error) => Ok(req.into_response(BotApiError::new(error).error_response())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::dataset::{MockChannelChat, MockMessageText, MockUser};
+
+    #[test]
+    fn test_chat_from_registry() {
+        let mut chats = Chats::default();
+        let channel = MockChannelChat::new().id(-1001).build();
+        chats.add_chat(channel.clone());
+
+        assert_eq!(chats.chat(&BodyChatId::Id(-1001)), channel);
+        // Unknown chats are guessed
+        assert!(chats.chat(&BodyChatId::Id(-1002)).is_supergroup());
+    }
+
+    #[test]
+    fn test_strict_check() {
+        let mut chats = Chats::default();
+        let unknown = json!({"chat_id": -1002, "user_id": 5});
+        assert_eq!(chats.check(&unknown), Ok(()));
+
+        chats.strict = true;
+        let message = MockMessageText::new().build();
+        chats.add_update(&Update {
+            id: teloxide::types::UpdateId(1),
+            kind: teloxide::types::UpdateKind::Message(message),
+        });
+        let known = json!({"chat_id": MockUser::ID, "user_id": MockUser::ID.to_string()});
+        assert_eq!(chats.check(&known), Ok(()));
+        assert_eq!(chats.check(&unknown), Err(ApiError::ChatNotFound));
+        let unknown_user = json!({"chat_id": MockUser::ID, "user_id": 5});
+        assert_eq!(chats.check(&unknown_user), Err(ApiError::UserNotFound));
+    }
+}
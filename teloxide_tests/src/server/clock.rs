@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// Bans and restrictions for less than this are forever
+const MIN_UNTIL_DATE: TimeDelta = TimeDelta::seconds(30);
+/// Bans and restrictions for more than this are forever
+const MAX_UNTIL_DATE: TimeDelta = TimeDelta::days(366);
+
+/// The time of the fake server. It goes along with the real time, but can be moved forward with
+/// [`MockBot::advance_time`], so that anything that depends on time can be tested without waiting,
+/// or stopped at some date with [`MockBot::set_time`]
+///
+/// [`MockBot::advance_time`]: crate::MockBot::advance_time
+/// [`MockBot::set_time`]: crate::MockBot::set_time
+#[derive(Default)]
+pub struct Clock {
+    fixed: Option<DateTime<Utc>>,
+    offset: TimeDelta,
+}
+
+impl Clock {
+    pub fn now(&self) -> DateTime<Utc> {
+        self.fixed.unwrap_or_else(Utc::now) + self.offset
+    }
+
+    /// Stops the clock at `time`. It will only move with [`Clock::advance`] after that
+    pub fn set(&mut self, time: DateTime<Utc>) {
+        self.fixed = Some(time);
+        self.offset = TimeDelta::zero();
+    }
+
+    pub fn advance(&mut self, duration: Duration) {
+        self.offset += TimeDelta::from_std(duration).expect("The duration is too large");
+    }
+
+    /// Moves the real time into the time of the clock. The mocked updates are made with the real
+    /// time, so their dates have to be moved forward as well. If the clock is stopped, the real
+    /// time has nothing to do with it, and the updates come at the time of the clock
+    pub fn shift(&self, date: DateTime<Utc>) -> DateTime<Utc> {
+        match self.fixed {
+            Some(_) => self.now(),
+            None => date + self.offset,
+        }
+    }
+
+    /// Turns the `until_date` of bans and restrictions into the time they end at, `None` meaning
+    /// forever, the same way telegram does it
+    pub fn until(&self, until_date: Option<i64>) -> Option<DateTime<Utc>> {
+        let until = DateTime::from_timestamp(until_date?, 0)?;
+        let duration = until - self.now();
+        if duration < MIN_UNTIL_DATE || duration > MAX_UNTIL_DATE {
+            return None;
+        }
+        Some(until)
+    }
+
+    /// Checks if the `until` from [`Clock::until`] is still in the future
+    pub fn is_before(&self, until: Option<DateTime<Utc>>) -> bool {
+        until.map_or(true, |until| self.now() < until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advance() {
+        let mut clock = Clock::default();
+        let before = clock.now();
+        clock.advance(Duration::from_secs(60 * 60));
+        assert!(clock.now() - before >= TimeDelta::hours(1));
+    }
+
+    #[test]
+    fn test_set() {
+        let mut clock = Clock::default();
+        let time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        clock.advance(Duration::from_secs(60));
+        clock.set(time);
+        assert_eq!(clock.now(), time);
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(clock.now(), time + TimeDelta::seconds(60));
+    }
+
+    #[test]
+    fn test_shift() {
+        let mut clock = Clock::default();
+        let date = Utc::now();
+        assert_eq!(clock.shift(date), date);
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(clock.shift(date), date + TimeDelta::seconds(60));
+
+        let time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        clock.set(time);
+        assert_eq!(clock.shift(date), time);
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(clock.shift(date), time + TimeDelta::seconds(60));
+    }
+
+    #[test]
+    fn test_until() {
+        let clock = Clock::default();
+        let now = clock.now().timestamp();
+        assert_eq!(clock.until(None), None);
+        assert_eq!(clock.until(Some(0)), None);
+        assert_eq!(clock.until(Some(now + 10)), None);
+        assert_eq!(clock.until(Some(now + 60 * 60 * 24 * 400)), None);
+        assert_eq!(
+            clock.until(Some(now + 60 * 60)),
+            DateTime::from_timestamp(now + 60 * 60, 0)
+        );
+    }
+
+    #[test]
+    fn test_is_before() {
+        let mut clock = Clock::default();
+        let until = clock.until(Some(clock.now().timestamp() + 60 * 60));
+        assert!(clock.is_before(until));
+        assert!(clock.is_before(None));
+        clock.advance(Duration::from_secs(2 * 60 * 60));
+        assert!(!clock.is_before(until));
+        assert!(clock.is_before(None));
+    }
+}
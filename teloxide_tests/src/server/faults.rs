@@ -0,0 +1,158 @@
+use std::sync::Mutex;
+
+use actix_web::{
+    body::BoxBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::ResponseError,
+    http::StatusCode,
+    middleware::Next,
+    web::Data,
+    HttpResponse,
+};
+use serde_json::Value;
+use teloxide::{types::ChatId, ApiError};
+
+use super::{
+    dropped_connection_body, method_name, normalize_method, request_body,
+    routes::{make_telegram_error, BotApiError},
+};
+use crate::state::State;
+
+/// How the fake server fails the request
+#[derive(Clone, Debug)]
+pub enum FaultKind {
+    /// Responds with the telegram error, like `ApiError::BotBlocked`
+    Api(ApiError),
+    /// Responds with the HTTP status code, like 502. Be aware that teloxide waits for 10 seconds
+    /// after any 5xx error before returning it
+    Status(u16),
+    /// Closes the connection without a proper response, like a network failure
+    DropConnection,
+}
+
+/// A failure of the calls to a method, added to the bot with [`MockBot::inject_fault`]
+///
+/// ```
+/// use teloxide::{types::ChatId, ApiError};
+/// use teloxide_tests::server::faults::{Fault, FaultKind};
+///
+/// // Fails the next SendMessage to the chat 1234
+/// Fault::new("SendMessage", FaultKind::Api(ApiError::BotBlocked)).chat_id(ChatId(1234));
+/// // Fails the 3rd EditMessageText with a 502
+/// Fault::new("EditMessageText", FaultKind::Status(502)).nth(3);
+/// // Drops the connection of the next two SendPhoto calls
+/// Fault::new("SendPhoto", FaultKind::DropConnection).times(2);
+/// ```
+///
+/// [`MockBot::inject_fault`]: crate::MockBot::inject_fault
+#[derive(Clone, Debug)]
+pub struct Fault {
+    pub method: String,
+    /// If set, only the calls to this chat are counted and failed
+    pub chat_id: Option<ChatId>,
+    /// The number of the matching call that fails first, starting from 1
+    pub nth: usize,
+    /// How many matching calls in a row fail, starting from the `nth`
+    pub times: usize,
+    pub kind: FaultKind,
+    /// How many matching calls were made since the fault was added
+    calls: usize,
+}
+
+impl Fault {
+    /// Fails the next call to the `method`
+    pub fn new(method: &str, kind: FaultKind) -> Self {
+        Self {
+            method: normalize_method(method),
+            chat_id: None,
+            nth: 1,
+            times: 1,
+            kind,
+            calls: 0,
+        }
+    }
+
+    pub fn chat_id(mut self, chat_id: ChatId) -> Self {
+        self.chat_id = Some(chat_id);
+        self
+    }
+
+    pub fn nth(mut self, nth: usize) -> Self {
+        self.nth = nth;
+        self
+    }
+
+    pub fn times(mut self, times: usize) -> Self {
+        self.times = times;
+        self
+    }
+
+    fn matches(&self, method: &str, body: &Value) -> bool {
+        if self.method != method {
+            return false;
+        }
+        let Some(ChatId(chat_id)) = self.chat_id else {
+            return true;
+        };
+        match &body["chat_id"] {
+            Value::Number(number) => number.as_i64() == Some(chat_id),
+            Value::String(string) => string == &chat_id.to_string(),
+            _ => false,
+        }
+    }
+
+    fn is_used_up(&self) -> bool {
+        self.calls >= self.nth + self.times - 1
+    }
+}
+
+/// Fails the request instead of calling the route, if one of the faults says so
+pub(crate) async fn inject_fault(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(state) = req.app_data::<Data<Mutex<State>>>().cloned() else {
+        return next.call(req).await;
+    };
+    let (Some(method), Some(body)) = (method_name(&req), request_body(&req)) else {
+        return next.call(req).await;
+    };
+    if !state
+        .lock()
+        .unwrap()
+        .faults
+        .iter()
+        .any(|fault| fault.method == method)
+    {
+        return next.call(req).await;
+    }
+
+    let mut fired = None;
+    {
+        let mut lock = state.lock().unwrap();
+        for fault in lock.faults.iter_mut() {
+            if !fault.matches(&method, &body.json) {
+                continue;
+            }
+            fault.calls += 1;
+            if fired.is_none() && fault.calls >= fault.nth {
+                fired = Some(fault.kind.clone());
+            }
+        }
+        lock.faults.retain(|fault| !fault.is_used_up());
+    }
+
+    let response = match fired {
+        None => return next.call(req).await,
+        Some(FaultKind::Api(error)) => BotApiError::new(error).error_response(),
+        Some(FaultKind::Status(status)) => {
+            let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            let description = status.canonical_reason().unwrap_or("Unknown error");
+            make_telegram_error(status, description.to_string())
+        }
+        Some(FaultKind::DropConnection) => HttpResponse::Ok().body(dropped_connection_body(
+            "Dropped by the fault injection".to_string(),
+        )),
+    };
+    Ok(req.into_response(response))
+}
@@ -0,0 +1,218 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use actix_web::{
+    body::BoxBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web::Data,
+};
+use chrono::{DateTime, TimeDelta, Utc};
+
+use super::{
+    chats::as_i64, method_name, request_body, routes::make_telegram_retry_after, ThrottledRequest,
+};
+use crate::state::State;
+
+/// Telegram allows about one message per second in a chat
+const CHAT_LIMIT: (usize, TimeDelta) = (1, TimeDelta::seconds(1));
+/// Telegram allows 20 messages per minute in a group
+const GROUP_LIMIT: (usize, TimeDelta) = (20, TimeDelta::minutes(1));
+/// Telegram allows about 30 messages per second for the whole bot
+const GLOBAL_LIMIT: (usize, TimeDelta) = (30, TimeDelta::seconds(1));
+
+/// The limits of telegram on sending messages, turned on with [`MockBot::enable_flood_control`].
+/// It goes by the time of the fake server, so [`MockBot::advance_time`] lifts the limits as well
+///
+/// [`MockBot::enable_flood_control`]: crate::MockBot::enable_flood_control
+/// [`MockBot::advance_time`]: crate::MockBot::advance_time
+#[derive(Default)]
+pub struct FloodControl {
+    /// The chats of the recently sent messages, with the time they were sent at
+    sent: VecDeque<(Option<i64>, DateTime<Utc>)>,
+}
+
+impl FloodControl {
+    /// Returns the seconds the bot has to wait if the message isn't allowed. If it is, its place
+    /// is taken right away, so the messages that are sent at the same time can't all fit into
+    /// the same place. The place is given back with [`FloodControl::release`] if the message
+    /// wasn't sent after all
+    pub fn reserve(&mut self, chat_id: Option<i64>, now: DateTime<Utc>) -> Result<(), u32> {
+        let longest_window = GROUP_LIMIT.1;
+        while self
+            .sent
+            .front()
+            .is_some_and(|(_, date)| now - *date >= longest_window)
+        {
+            self.sent.pop_front();
+        }
+
+        let mut wait = self.wait(GLOBAL_LIMIT, now, |_| true);
+        if let Some(chat_id) = chat_id {
+            let in_chat = |chat: Option<i64>| chat == Some(chat_id);
+            wait = wait.max(self.wait(CHAT_LIMIT, now, in_chat));
+            // Only groups have negative ids
+            if chat_id < 0 {
+                wait = wait.max(self.wait(GROUP_LIMIT, now, in_chat));
+            }
+        }
+
+        if wait > TimeDelta::zero() {
+            // Telegram only tells the whole seconds, rounded up
+            let seconds = wait.num_seconds() + i64::from(wait.subsec_nanos() > 0);
+            return Err(seconds.max(1) as u32);
+        }
+        self.sent.push_back((chat_id, now));
+        Ok(())
+    }
+
+    /// Gives back the place of the message that was reserved, but not sent
+    pub fn release(&mut self, chat_id: Option<i64>, now: DateTime<Utc>) {
+        if let Some(index) = self.sent.iter().rposition(|sent| *sent == (chat_id, now)) {
+            self.sent.remove(index);
+        }
+    }
+
+    /// How long it is until one more message fits into the `limit`
+    fn wait(
+        &self,
+        (count, window): (usize, TimeDelta),
+        now: DateTime<Utc>,
+        filter: impl Fn(Option<i64>) -> bool,
+    ) -> TimeDelta {
+        let in_window: Vec<DateTime<Utc>> = self
+            .sent
+            .iter()
+            .filter(|(chat, date)| filter(*chat) && now - *date < window)
+            .map(|(_, date)| *date)
+            .collect();
+        if in_window.len() < count {
+            return TimeDelta::zero();
+        }
+        // The oldest messages have to leave the window for the new one to fit
+        in_window[in_window.len() - count] + window - now
+    }
+}
+
+/// Only the methods that send messages are limited
+fn is_limited(method: &str) -> bool {
+    (method.starts_with("Send") && method != "SendChatAction")
+        || matches!(
+            method,
+            "ForwardMessage" | "ForwardMessages" | "CopyMessage" | "CopyMessages"
+        )
+}
+
+/// Responds with `RetryAfter` instead of calling the route, if the flood control is on and the
+/// bot is sending too much. Only the messages the route actually sent count towards the limits,
+/// the places of the failed ones are given back
+pub(crate) async fn limit(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(state) = req.app_data::<Data<Mutex<State>>>().cloned() else {
+        return next.call(req).await;
+    };
+    let Some(method) = method_name(&req).filter(|method| is_limited(method)) else {
+        return next.call(req).await;
+    };
+    if state.lock().unwrap().flood_control.is_none() {
+        return next.call(req).await;
+    }
+
+    let Some(body) = request_body(&req) else {
+        return next.call(req).await;
+    };
+    let chat_id = as_i64(&body.json["chat_id"]);
+    let now = state.lock().unwrap().clock.now();
+    let retry_after = {
+        let mut lock = state.lock().unwrap();
+        let State {
+            flood_control,
+            responses,
+            ..
+        } = &mut *lock;
+        let retry_after = flood_control
+            .as_mut()
+            .and_then(|flood_control| flood_control.reserve(chat_id, now).err());
+        if let Some(retry_after) = retry_after {
+            responses.throttled_requests.push(ThrottledRequest {
+                method,
+                chat_id,
+                retry_after,
+                date: now,
+            });
+        }
+        retry_after
+    };
+
+    if let Some(retry_after) = retry_after {
+        return Ok(req.into_response(make_telegram_retry_after(retry_after)));
+    }
+    let response = next.call(req).await;
+    if !response
+        .as_ref()
+        .is_ok_and(|response| response.status().is_success())
+    {
+        if let Some(flood_control) = &mut state.lock().unwrap().flood_control {
+            flood_control.release(chat_id, now);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start() -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000, 0).unwrap()
+    }
+
+    #[test]
+    fn test_chat_limit() {
+        let mut flood_control = FloodControl::default();
+        assert_eq!(flood_control.reserve(Some(1), start()), Ok(()));
+        assert_eq!(flood_control.reserve(Some(1), start()), Err(1));
+        // Other chats are fine
+        assert_eq!(flood_control.reserve(Some(2), start()), Ok(()));
+        assert_eq!(
+            flood_control.reserve(Some(1), start() + TimeDelta::seconds(1)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_group_limit() {
+        let mut flood_control = FloodControl::default();
+        for i in 0..20 {
+            let now = start() + TimeDelta::seconds(i);
+            assert_eq!(flood_control.reserve(Some(-100), now), Ok(()));
+        }
+        let now = start() + TimeDelta::seconds(20);
+        assert_eq!(flood_control.reserve(Some(-100), now), Err(40));
+        assert_eq!(
+            flood_control.reserve(Some(-100), start() + TimeDelta::seconds(60)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_global_limit() {
+        let mut flood_control = FloodControl::default();
+        for chat_id in 0..30 {
+            assert_eq!(flood_control.reserve(Some(chat_id), start()), Ok(()));
+        }
+        assert_eq!(flood_control.reserve(Some(30), start()), Err(1));
+        assert_eq!(flood_control.reserve(None, start()), Err(1));
+    }
+
+    #[test]
+    fn test_release() {
+        let mut flood_control = FloodControl::default();
+        assert_eq!(flood_control.reserve(Some(1), start()), Ok(()));
+        assert_eq!(flood_control.reserve(Some(1), start()), Err(1));
+        // The message wasn't sent, so the next one can take its place
+        flood_control.release(Some(1), start());
+        assert_eq!(flood_control.reserve(Some(1), start()), Ok(()));
+    }
+}
@@ -0,0 +1,185 @@
+use actix_web::{
+    body::BoxBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::ResponseError,
+    middleware::Next,
+};
+use teloxide::{
+    types::{InlineKeyboardButtonKind, InputPollOption, ReplyMarkup},
+    ApiError,
+};
+
+use super::{request_body, routes::BotApiError};
+
+/// The longest text of a message, in UTF-16 code units after the entities are parsed
+pub const MAX_TEXT_LENGTH: usize = 4096;
+/// The longest caption of a media message
+pub const MAX_CAPTION_LENGTH: usize = 1024;
+/// The longest `callback_data` of an inline button, in bytes
+pub const MAX_CALLBACK_DATA_SIZE: usize = 64;
+/// The most buttons an inline keyboard can have
+pub const MAX_INLINE_BUTTONS: usize = 100;
+pub const MIN_POLL_OPTIONS: usize = 2;
+pub const MAX_POLL_OPTIONS: usize = 10;
+pub const MAX_POLL_QUESTION_LENGTH: usize = 300;
+pub const MAX_POLL_OPTION_LENGTH: usize = 100;
+/// The biggest request the bot API accepts, so the biggest file a bot can upload
+pub const MAX_UPLOAD_SIZE: usize = 50 * 1024 * 1024;
+
+/// Telegram counts the length of the texts in UTF-16 code units
+fn length(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Checks the text of a message. The error is different for the edits, so it is passed in
+pub(crate) fn check_text(text: &str, too_long: ApiError) -> Result<(), BotApiError> {
+    if text.trim().is_empty() {
+        return Err(BotApiError::new(ApiError::MessageTextIsEmpty));
+    }
+    if length(text) > MAX_TEXT_LENGTH {
+        return Err(BotApiError::new(too_long));
+    }
+    Ok(())
+}
+
+/// The error telegram gives for a caption that is too long in a new message
+pub(crate) fn caption_is_too_long() -> ApiError {
+    ApiError::Unknown("Bad Request: message caption is too long".to_string())
+}
+
+/// Checks the caption of a media message. Like with [`check_text`], the error is different for the
+/// edits
+pub(crate) fn check_caption(caption: Option<&str>, too_long: ApiError) -> Result<(), BotApiError> {
+    if caption.is_some_and(|caption| length(caption) > MAX_CAPTION_LENGTH) {
+        return Err(BotApiError::new(too_long));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_poll(question: &str, options: &[InputPollOption]) -> Result<(), BotApiError> {
+    let error = if question.trim().is_empty() {
+        ApiError::PollQuestionMustBeNonEmpty
+    } else if length(question) > MAX_POLL_QUESTION_LENGTH {
+        ApiError::PollQuestionLengthTooLong
+    } else if options.len() < MIN_POLL_OPTIONS {
+        ApiError::PollMustHaveMoreOptions
+    } else if options.len() > MAX_POLL_OPTIONS {
+        ApiError::PollCantHaveMoreOptions
+    } else if options.iter().any(|option| option.text.trim().is_empty()) {
+        ApiError::PollOptionsMustBeNonEmpty
+    } else if options
+        .iter()
+        .any(|option| length(&option.text) > MAX_POLL_OPTION_LENGTH)
+    {
+        ApiError::PollOptionsLengthTooLong
+    } else {
+        return Ok(());
+    };
+    Err(BotApiError::new(error))
+}
+
+/// Every method with the `reply_markup` has the same limits on the inline keyboard
+fn check_reply_markup(reply_markup: &ReplyMarkup) -> Result<(), ApiError> {
+    let ReplyMarkup::InlineKeyboard(markup) = reply_markup else {
+        return Ok(());
+    };
+    let buttons = markup.inline_keyboard.iter().flatten();
+    if buttons.clone().count() > MAX_INLINE_BUTTONS {
+        return Err(ApiError::Unknown(
+            "Bad Request: REPLY_MARKUP_TOO_LONG".to_string(),
+        ));
+    }
+    for button in buttons {
+        if let InlineKeyboardButtonKind::CallbackData(data) = &button.kind {
+            if data.is_empty() || data.len() > MAX_CALLBACK_DATA_SIZE {
+                return Err(ApiError::ButtonDataInvalid);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Rejects the requests that are too big for telegram, or have an inline keyboard over the limits,
+/// before they get to the route
+pub(crate) async fn check_request(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(body) = request_body(&req) else {
+        return next.call(req).await;
+    };
+
+    let error = if body.bytes.len() > MAX_UPLOAD_SIZE {
+        Some(ApiError::RequestEntityTooLarge)
+    } else {
+        serde_json::from_value::<ReplyMarkup>(body.json["reply_markup"].clone())
+            .ok()
+            .and_then(|reply_markup| check_reply_markup(&reply_markup).err())
+    };
+
+    match error {
+        Some(error) => Ok(req.into_response(BotApiError::new(error).error_response())),
+        None => next.call(req).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+    use super::*;
+
+    #[test]
+    fn test_text_length_is_in_utf16() {
+        // Every crab takes two UTF-16 code units
+        let crabs = "🦀".repeat(MAX_TEXT_LENGTH / 2);
+        assert!(check_text(&crabs, ApiError::MessageIsTooLong).is_ok());
+        let too_long = crabs + "a";
+        assert_eq!(
+            check_text(&too_long, ApiError::MessageIsTooLong),
+            Err(BotApiError::new(ApiError::MessageIsTooLong))
+        );
+    }
+
+    #[test]
+    fn test_poll() {
+        let options = |count: usize| vec![InputPollOption::new("option"); count];
+        assert!(check_poll("question", &options(MAX_POLL_OPTIONS)).is_ok());
+        assert_eq!(
+            check_poll("question", &options(MAX_POLL_OPTIONS + 1)),
+            Err(BotApiError::new(ApiError::PollCantHaveMoreOptions))
+        );
+        assert_eq!(
+            check_poll("question", &options(1)),
+            Err(BotApiError::new(ApiError::PollMustHaveMoreOptions))
+        );
+        assert_eq!(
+            check_poll(" ", &options(2)),
+            Err(BotApiError::new(ApiError::PollQuestionMustBeNonEmpty))
+        );
+    }
+
+    #[test]
+    fn test_reply_markup() {
+        let markup = |buttons: Vec<InlineKeyboardButton>| {
+            ReplyMarkup::InlineKeyboard(InlineKeyboardMarkup::new(
+                buttons.chunks(8).map(|row| row.to_vec()),
+            ))
+        };
+        let button = |data: &str| InlineKeyboardButton::callback("button", data);
+
+        assert!(check_reply_markup(&markup(vec![button("data"); MAX_INLINE_BUTTONS])).is_ok());
+        assert_eq!(
+            check_reply_markup(&markup(vec![button("data"); MAX_INLINE_BUTTONS + 1])),
+            Err(ApiError::Unknown(
+                "Bad Request: REPLY_MARKUP_TOO_LONG".to_string()
+            ))
+        );
+        assert_eq!(
+            check_reply_markup(&markup(vec![button(
+                &"a".repeat(MAX_CALLBACK_DATA_SIZE + 1)
+            )])),
+            Err(ApiError::ButtonDataInvalid)
+        );
+    }
+}
@@ -1,29 +1,63 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use serde::Serialize;
-use teloxide::types::{Message, ReplyMarkup};
+use teloxide::types::{ChatId, Message, ReplyMarkup};
 
+/// The messages of one chat, by their ids
 #[derive(Default)]
-pub struct Messages {
-    pub messages: Vec<Message>,
+struct ChatMessages {
+    messages: BTreeMap<i32, Message>,
     last_message_id: i32,
 }
 
+/// All of the messages the fake server knows about. Like in telegram, every chat has its own
+/// message ids
+#[derive(Default)]
+pub struct Messages {
+    chats: HashMap<ChatId, ChatMessages>,
+}
+
 impl Messages {
-    pub fn max_message_id(&self) -> i32 {
-        self.last_message_id
+    /// The id of the last message in the chat, 0 if there are none
+    pub fn max_message_id(&self, chat_id: ChatId) -> i32 {
+        self.chats
+            .get(&chat_id)
+            .map_or(0, |chat| chat.last_message_id)
     }
 
-    pub fn edit_message(&mut self, message: Message) -> Option<Message> {
-        self.messages.iter().find(|m| m.id == message.id)?; // Find the message (return None if not found)
+    /// The messages of the chat as they are now, in the order they were sent, with edits applied
+    /// and without the deleted ones
+    pub fn chat_history(&self, chat_id: ChatId) -> Vec<Message> {
+        self.chats
+            .get(&chat_id)
+            .map(|chat| chat.messages.values().cloned().collect())
+            .unwrap_or_default()
+    }
 
-        self.messages.retain(|m| m.id != message.id); // Remove the old message
-        self.messages.push(message.clone()); // Add the new message
-        Some(message) // Profit!
+    /// Every message of every chat
+    pub fn iter(&self) -> impl Iterator<Item = &Message> {
+        self.chats.values().flat_map(|chat| chat.messages.values())
+    }
+
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut Message> {
+        self.chats
+            .values_mut()
+            .flat_map(|chat| chat.messages.values_mut())
+    }
+
+    pub fn edit_message(&mut self, message: Message) -> Option<Message> {
+        let old_message = self
+            .chats
+            .get_mut(&message.chat.id)?
+            .messages
+            .get_mut(&message.id.0)?;
+        *old_message = message.clone();
+        Some(message)
     }
 
     pub fn edit_message_field<T>(
         &mut self,
+        chat_id: ChatId,
         message_id: i32,
         field: &str,
         value: T,
@@ -31,65 +65,63 @@ impl Messages {
     where
         T: Serialize,
     {
-        let message = self.messages.iter().find(|m| m.id.0 == message_id)?; // Find the message
-                                                                            // (return None if not found)
+        let message = self.get_message(chat_id, message_id)?;
 
         let mut json = serde_json::to_value(message).ok()?; // Convert the message to JSON
         json[field] = serde_json::to_value(value).ok()?; // Edit the field
         let new_message: Message = serde_json::from_value(json).ok()?; // Convert back to Message
 
-        self.messages.retain(|m| m.id.0 != message_id); // Remove the old message
-        self.messages.push(new_message.clone()); // Add the new message
-        Some(new_message) // Profit!
+        self.edit_message(new_message)
     }
 
     pub fn edit_message_reply_markup(
         &mut self,
+        chat_id: ChatId,
         message_id: i32,
         reply_markup: Option<ReplyMarkup>,
     ) -> Option<Message> {
         match reply_markup {
             None => {
                 // Telegram deletes reply markup when `editMessageText` is called without any.
-                self.edit_message_field(message_id, "reply_markup", None::<()>)
+                self.edit_message_field(chat_id, message_id, "reply_markup", None::<()>)
             }
             // Only the inline keyboard can be inside of a message
             Some(ReplyMarkup::InlineKeyboard(reply_markup)) => {
-                self.edit_message_field(message_id, "reply_markup", reply_markup)
+                self.edit_message_field(chat_id, message_id, "reply_markup", reply_markup)
             }
-            _ => unreachable!("Only InlineKeyboard is allowed"),
+            // The reply keyboards aren't a part of the message, they are shown to the users of
+            // the chat instead
+            Some(_) => self.get_message(chat_id, message_id),
         }
     }
 
     pub fn add_message(&mut self, message: Message) -> Message {
-        self.messages.push(message.clone());
-        self.last_message_id += 1;
+        let chat = self.chats.entry(message.chat.id).or_default();
+        chat.last_message_id = chat.last_message_id.max(message.id.0);
+        chat.messages.insert(message.id.0, message.clone());
         message
     }
 
-    pub fn get_message(&self, message_id: i32) -> Option<Message> {
-        self.messages.iter().find(|m| m.id.0 == message_id).cloned()
+    pub fn get_message(&self, chat_id: ChatId, message_id: i32) -> Option<Message> {
+        self.chats.get(&chat_id)?.messages.get(&message_id).cloned()
     }
 
-    pub fn delete_message(&mut self, message_id: i32) -> Option<Message> {
-        let message = self
-            .messages
-            .iter()
-            .find(|m| m.id.0 == message_id)
-            .cloned()?;
-        self.messages.retain(|m| m.id.0 != message_id);
-        Some(message)
+    pub fn delete_message(&mut self, chat_id: ChatId, message_id: i32) -> Option<Message> {
+        self.chats.get_mut(&chat_id)?.messages.remove(&message_id)
     }
 
-    pub fn delete_messages(&mut self, message_ids: &[i32]) -> Vec<Message> {
+    pub fn delete_messages(&mut self, chat_id: ChatId, message_ids: &[i32]) -> Vec<Message> {
         let message_ids: HashSet<i32> = message_ids.iter().cloned().collect();
-        let deleted = self
+        let Some(chat) = self.chats.get_mut(&chat_id) else {
+            return vec![];
+        };
+        let deleted = chat
             .messages
-            .iter()
+            .values()
             .filter(|m| message_ids.contains(&m.id.0))
             .cloned()
             .collect();
-        self.messages.retain(|m| !message_ids.contains(&m.id.0));
+        chat.messages.retain(|id, _| !message_ids.contains(id));
         deleted
     }
 }
@@ -103,6 +135,10 @@ mod tests {
     use super::*;
     use crate::dataset::*;
 
+    fn chat_id() -> ChatId {
+        ChatId(MockUser::ID as i64)
+    }
+
     #[test]
     #[serial]
     fn test_add_messages() {
@@ -125,7 +161,7 @@ mod tests {
                 .id(3)
                 .build(),
         );
-        assert_eq!(messages.max_message_id(), 3);
+        assert_eq!(messages.max_message_id(chat_id()), 3);
     }
 
     #[test]
@@ -146,7 +182,7 @@ mod tests {
                 .id(1)
                 .build(),
         );
-        let message = messages.get_message(1).unwrap();
+        let message = messages.get_message(chat_id(), 1).unwrap();
         assert_eq!(message.text().unwrap(), "321");
         assert_eq!(message.edit_date().unwrap(), &date);
     }
@@ -161,8 +197,11 @@ mod tests {
                 .id(1)
                 .build(),
         );
-        messages.edit_message_field(1, "text", "1234");
-        assert_eq!(messages.get_message(1).unwrap().text().unwrap(), "1234");
+        messages.edit_message_field(chat_id(), 1, "text", "1234");
+        assert_eq!(
+            messages.get_message(chat_id(), 1).unwrap().text().unwrap(),
+            "1234"
+        );
     }
 
     #[test]
@@ -175,7 +214,10 @@ mod tests {
                 .id(1)
                 .build(),
         );
-        assert_eq!(messages.get_message(1).unwrap().text().unwrap(), "123");
+        assert_eq!(
+            messages.get_message(chat_id(), 1).unwrap().text().unwrap(),
+            "123"
+        );
     }
 
     #[test]
@@ -188,8 +230,8 @@ mod tests {
                 .id(1)
                 .build(),
         );
-        messages.delete_message(1);
-        assert_eq!(messages.get_message(1), None);
+        messages.delete_message(chat_id(), 1);
+        assert_eq!(messages.get_message(chat_id(), 1), None);
     }
 
     #[test]
@@ -205,17 +247,17 @@ mod tests {
             );
         }
 
-        let deleted = messages.delete_messages(&[2, 3]);
+        let deleted = messages.delete_messages(chat_id(), &[2, 3]);
 
         assert_eq!(deleted.len(), 2);
         assert_eq!(deleted[0].id, MessageId(2));
         assert_eq!(deleted[1].id, MessageId(3));
 
-        assert!(messages.get_message(1).is_some());
-        assert_eq!(messages.get_message(2), None);
-        assert_eq!(messages.get_message(3), None);
-        assert!(messages.get_message(4).is_some());
-        assert!(messages.get_message(5).is_some());
+        assert!(messages.get_message(chat_id(), 1).is_some());
+        assert_eq!(messages.get_message(chat_id(), 2), None);
+        assert_eq!(messages.get_message(chat_id(), 3), None);
+        assert!(messages.get_message(chat_id(), 4).is_some());
+        assert!(messages.get_message(chat_id(), 5).is_some());
     }
 
     #[test]
@@ -229,6 +271,7 @@ mod tests {
                 .build(),
         );
         messages.edit_message_reply_markup(
+            chat_id(),
             1,
             Some(ReplyMarkup::InlineKeyboard(InlineKeyboardMarkup::new(
                 vec![vec![InlineKeyboardButton::callback("123", "123")]],
@@ -236,7 +279,7 @@ mod tests {
         );
         assert_eq!(
             messages
-                .get_message(1)
+                .get_message(chat_id(), 1)
                 .unwrap()
                 .reply_markup()
                 .unwrap()
@@ -245,4 +288,48 @@ mod tests {
             "123"
         );
     }
+
+    #[test]
+    #[serial]
+    fn test_ids_are_per_chat() {
+        let mut messages = Messages::default();
+        let other_chat = MockGroupChat::new().build();
+        messages.add_message(MockMessageText::new().id(1).build());
+        messages.add_message(MockMessageText::new().id(2).build());
+        messages.add_message(
+            MockMessageText::new()
+                .id(1)
+                .chat(other_chat.clone())
+                .build(),
+        );
+
+        assert_eq!(messages.max_message_id(chat_id()), 2);
+        assert_eq!(messages.max_message_id(other_chat.id), 1);
+        messages.delete_message(other_chat.id, 1);
+        assert!(messages.get_message(chat_id(), 1).is_some());
+    }
+
+    #[test]
+    #[serial]
+    fn test_chat_history() {
+        let mut messages = Messages::default();
+        for id in 1..=3 {
+            messages.add_message(
+                MockMessageText::new()
+                    .text(format!("Message {}", id))
+                    .id(id)
+                    .build(),
+            );
+        }
+        messages.edit_message_field(chat_id(), 1, "text", "Edited");
+        messages.delete_message(chat_id(), 2);
+
+        let texts: Vec<_> = messages
+            .chat_history(chat_id())
+            .iter()
+            .map(|message| message.text().unwrap().to_string())
+            .collect();
+        assert_eq!(texts, ["Edited", "Message 3"]);
+        assert!(messages.chat_history(ChatId(-1)).is_empty());
+    }
 }
@@ -1,27 +1,42 @@
 //! A fake telegram bot API for testing purposes. Read more in teloxide_tests crate.
+mod admin;
 mod routes;
 use std::{
     error::Error,
     io,
-    net::TcpListener,
-    sync::{Arc, Mutex},
+    net::{TcpListener, ToSocketAddrs},
+    rc::Rc,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Instant,
 };
 
+use actix_multipart::Multipart;
 use actix_web::{
-    web::{self, get, post, scope, Data, ServiceConfig},
-    App, HttpResponse, HttpServer, Responder,
+    body::{self, BodyStream, BoxBody},
+    dev::{Payload, ServerHandle, Service, ServiceRequest, ServiceResponse},
+    http::{
+        header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE},
+        Uri,
+    },
+    middleware::{from_fn, Next},
+    rt::System,
+    web::{self, get, post, scope, Bytes, Data, ServiceConfig},
+    App, HttpMessage, HttpResponse, HttpServer, Responder,
 };
+use futures_util::{stream, StreamExt};
 pub use responses::*;
 use routes::{
     answer_callback_query::*, ban_chat_member::*, copy_message::*, delete_message::*,
-    delete_messages::*, download_file::download_file, edit_message_caption::*,
+    delete_messages::*, delete_webhook::*, download_file::download_file, edit_message_caption::*,
     edit_message_reply_markup::*, edit_message_text::*, forward_message::*, get_file::*, get_me::*,
-    get_updates::*, get_webhook_info::*, pin_chat_message::*, restrict_chat_member::*,
-    send_animation::*, send_audio::*, send_chat_action::*, send_contact::*, send_dice::*,
-    send_document::*, send_invoice::*, send_location::*, send_media_group::*, send_message::*,
-    send_photo::*, send_poll::*, send_sticker::*, send_venue::*, send_video::*, send_video_note::*,
-    send_voice::*, set_message_reaction::*, set_my_commands::*, unban_chat_member::*,
-    unpin_all_chat_messages::*, unpin_chat_message::*,
+    get_raw_multipart_fields, get_updates::*, get_webhook_info::*, pin_chat_message::*,
+    restrict_chat_member::*, send_animation::*, send_audio::*, send_chat_action::*,
+    send_contact::*, send_dice::*, send_document::*, send_invoice::*, send_location::*,
+    send_media_group::*, send_message::*, send_photo::*, send_poll::*, send_sticker::*,
+    send_venue::*, send_video::*, send_video_note::*, send_voice::*, set_message_reaction::*,
+    set_my_commands::*, set_webhook::*, unban_chat_member::*, unpin_all_chat_messages::*,
+    unpin_chat_message::*,
 };
 pub use routes::{
     copy_message::CopyMessageBody, delete_message::DeleteMessageBody,
@@ -35,74 +50,99 @@ pub use routes::{
     send_photo::SendMessagePhotoBody, send_poll::SendMessagePollBody,
     send_sticker::SendMessageStickerBody, send_venue::SendMessageVenueBody,
     send_video::SendMessageVideoBody, send_video_note::SendMessageVideoNoteBody,
+    set_webhook::SetWebhookBody,
 };
+use serde_json::{Map, Value};
 use teloxide::types::Me;
-use tokio::{
-    sync::mpsc::{channel, Sender},
-    task::{JoinError, JoinHandle},
-};
-use tokio_util::sync::CancellationToken;
 
 use crate::state::State;
 
+pub mod chats;
+pub mod clock;
+pub mod faults;
+pub mod flood_control;
+pub mod limits;
 pub mod messages;
+pub mod parse_mode;
+pub mod permissions;
+pub mod random;
+pub mod reply_keyboards;
 pub mod responses;
+pub mod stubs;
+pub mod update_queue;
+pub mod usernames;
+pub(crate) mod webhook;
 
 pub(crate) struct ServerManager {
     pub port: u16,
-    server: JoinHandle<()>,
-    cancel_token: CancellationToken,
+    /// The `me` that the server was started with
+    pub me: Me,
+    server_handle: ServerHandle,
+    thread: Option<thread::JoinHandle<()>>,
 }
 
 #[warn(clippy::unwrap_used)]
 impl ServerManager {
-    pub(crate) async fn start(me: Me, state: Arc<Mutex<State>>) -> Result<Self, Box<dyn Error>> {
+    /// Starts the server on its own thread, so that it doesn't depend on the runtime of the
+    /// caller, and can outlive any of the dispatches
+    pub(crate) fn start(me: Me, state: Arc<Mutex<State>>) -> Result<Self, Box<dyn Error>> {
         let listener = TcpListener::bind("127.0.0.1:0")?;
         let port = listener.local_addr()?.port();
 
-        let cancel_token = CancellationToken::new();
-        let (tx, mut rx) = channel::<()>(100);
-
-        let server = tokio::spawn(run_server(
-            listener,
-            me,
-            state.clone(),
-            cancel_token.clone(),
-            tx,
-        ));
+        let (tx, rx) = mpsc::channel();
+        let server_me = me.clone();
+        let thread = thread::spawn(move || {
+            System::new().block_on(async move {
+                let server = match create_server(listener, server_me, state) {
+                    Ok(server) => server,
+                    Err(err) => return log::error!("Couldn't start the fake server: {err}"),
+                };
+                if tx.send(server.handle()).is_err() {
+                    return;
+                }
+                if let Err(err) = server.await {
+                    log::error!("The fake server stopped with an error: {err}");
+                }
+            })
+        });
         // Waits until the server is ready
-        rx.recv().await;
+        let server_handle = rx.recv()?;
 
         Ok(Self {
             port,
-            cancel_token,
-            server,
+            me,
+            server_handle,
+            thread: Some(thread),
         })
     }
+}
 
-    pub(crate) async fn stop(self) -> Result<(), JoinError> {
-        self.cancel_token.cancel();
-        self.server.await
+impl Drop for ServerManager {
+    fn drop(&mut self) {
+        // The stop command is sent right away, the returned future only waits for it to finish,
+        // which joining the thread does as well
+        drop(self.server_handle.stop(false));
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
     }
 }
 
-async fn run_server(
-    listener: TcpListener,
-    me: Me,
-    state: Arc<Mutex<State>>,
-    cancel_token: CancellationToken,
-    tx: Sender<()>,
-) {
-    let server = create_server(listener, me, state).unwrap();
-    tx.send(()).await.unwrap();
-    let server_handle = server.handle();
-
-    tokio::spawn(async move {
-        cancel_token.cancelled().await;
-        server_handle.stop(false).await;
-    });
-
-    server.await.unwrap();
+/// Runs the fake server by itself on `address`, until the process is stopped. Any bot (even one
+/// that isn't written in rust) can use it by setting its api url to `http://<address>`.
+///
+/// Besides the bot API, the server has admin endpoints to control it:
+/// - `POST /admin/updates` takes an update or a list of updates in the telegram format, and sends
+///   them to the webhook if it is set, or puts them in the `getUpdates` queue otherwise
+/// - `GET /admin/responses` returns the [`Responses`] as JSON
+/// - `GET /admin/messages` returns all of the messages the server knows about as JSON
+/// - `POST /admin/reset` clears the responses
+///
+/// This is what the `teloxide_tests` binary (behind the `server` feature) runs.
+pub async fn run_standalone<A: ToSocketAddrs>(address: A, me: Me) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    let state = Arc::new(Mutex::new(State::default()));
+    create_server(listener, me, state)?.await
 }
 
 fn create_server(
@@ -111,24 +151,300 @@ fn create_server(
     state: Arc<Mutex<State>>,
 ) -> io::Result<actix_web::dev::Server> {
     Ok(HttpServer::new(move || {
+        let activity_state = state.clone();
         App::new()
             .app_data(Data::new(me.clone()))
             .app_data(Data::from(state.clone()))
+            .wrap(from_fn(stubs::call_stub))
+            .wrap(from_fn(flood_control::limit))
+            .wrap(from_fn(permissions::check_request))
+            .wrap(from_fn(chats::check_request))
+            .wrap(from_fn(faults::inject_fault))
+            .wrap(from_fn(usernames::resolve_chat_ids))
+            .wrap(from_fn(limits::check_request))
+            .wrap(from_fn(record_raw_request))
+            .wrap(from_fn(parse_body))
+            .wrap_fn(move |mut req, srv| {
+                normalize_method_name(&mut req);
+                // Long polling doesn't count as the bot doing something
+                let is_polling = req.path().ends_with("/GetUpdates");
+                let state = activity_state.clone();
+                if !is_polling {
+                    state.lock().unwrap().last_request_at = Some(Instant::now());
+                }
+                let response = srv.call(req);
+                async move {
+                    let response = response.await;
+                    if !is_polling {
+                        state.lock().unwrap().last_request_at = Some(Instant::now());
+                    }
+                    response
+                }
+            })
             .configure(set_routes)
     })
     .listen(listener)?
     .run())
 }
 
+/// Telegram doesn't care about the case of the method names. Teloxide calls `/SendMessage`, but
+/// most of the other libraries call `/sendMessage`, so the first letter is always uppercased to
+/// match the routes
+fn normalize_method_name(req: &mut ServiceRequest) {
+    let Some((prefix, method)) = req.path().rsplit_once('/') else {
+        return;
+    };
+    if !prefix.starts_with("/bot") || !method.starts_with(|c: char| c.is_ascii_lowercase()) {
+        return;
+    }
+    let path = format!("{prefix}/{}", normalize_method(method));
+
+    let mut parts = req.head().uri.clone().into_parts();
+    let path_and_query = match req.query_string() {
+        "" => path,
+        query => format!("{path}?{query}"),
+    };
+    let Ok(path_and_query) = path_and_query.parse() else {
+        return;
+    };
+    parts.path_and_query = Some(path_and_query);
+    let Ok(uri) = Uri::from_parts(parts) else {
+        return;
+    };
+    req.match_info_mut().get_mut().update(&uri);
+    req.head_mut().uri = uri;
+}
+
+/// Returns the name of the bot API method of the request, like `SendMessage`
+fn method_name(req: &ServiceRequest) -> Option<String> {
+    req.path()
+        .strip_prefix("/bot")
+        .and_then(|path| path.split_once('/'))
+        .map(|(_, method)| method.to_string())
+}
+
+/// Reads the bytes of the request. The payload can only be read once, so it is put back for the
+/// route after reading it
+async fn read_payload(req: &mut ServiceRequest) -> Result<Bytes, actix_web::Error> {
+    let mut payload = req.take_payload();
+    let mut raw_body = Vec::new();
+    while let Some(chunk) = payload.next().await {
+        raw_body.extend_from_slice(&chunk?);
+    }
+    let raw_body = Bytes::from(raw_body);
+    req.set_payload(Payload::from(raw_body.clone()));
+    Ok(raw_body)
+}
+
+/// The body of a bot API request. It is read and parsed only once, by [`parse_body`], and the
+/// middlewares get it from the extensions of the request with [`request_body`]
+pub(crate) struct RequestBody {
+    /// The bytes of the body, as the route gets them
+    pub bytes: Bytes,
+    pub raw: RawRequestBody,
+    /// The body as JSON, see [`raw_body_to_json`]
+    pub json: Value,
+}
+
+impl RequestBody {
+    fn new(bytes: Bytes, raw: RawRequestBody) -> Self {
+        let json = raw_body_to_json(&raw);
+        Self { bytes, raw, json }
+    }
+
+    /// Reads the body of the request, and puts it back for the route
+    async fn read(req: &mut ServiceRequest) -> Result<Self, actix_web::Error> {
+        let bytes = read_payload(req).await?;
+
+        let is_multipart = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| content_type.starts_with("multipart/form-data"));
+        if is_multipart {
+            let stream_bytes = bytes.clone();
+            let mut multipart =
+                Multipart::new(req.headers(), stream::once(async { Ok(stream_bytes) }));
+            let (fields, attachments) = get_raw_multipart_fields(&mut multipart).await;
+            // Sorted, so that the same requests look the same
+            let raw = RawRequestBody::Multipart {
+                fields: fields.into_iter().collect(),
+                attachments: attachments.into_iter().collect(),
+            };
+            return Ok(Self::new(bytes, raw));
+        }
+        let raw = serde_json::from_slice(&bytes).map_or_else(
+            |_| RawRequestBody::Other(String::from_utf8_lossy(&bytes).to_string()),
+            RawRequestBody::Json,
+        );
+        Ok(Self::new(bytes, raw))
+    }
+}
+
+/// The body of the request, if it is a request to the bot API
+fn request_body(req: &ServiceRequest) -> Option<Rc<RequestBody>> {
+    req.extensions().get::<Rc<RequestBody>>().cloned()
+}
+
+/// Makes the middlewares after this one, and the route, see the new `body`
+fn set_request_body(req: &mut ServiceRequest, body: RequestBody) {
+    req.headers_mut()
+        .insert(CONTENT_LENGTH, HeaderValue::from(body.bytes.len()));
+    req.set_payload(Payload::from(body.bytes.clone()));
+    req.extensions_mut().insert(Rc::new(body));
+}
+
+/// Reads the body of the bot API requests for all the other middlewares, so they don't have to
+/// read and parse it again
+async fn parse_body(
+    mut req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    if method_name(&req).is_some() {
+        let body = RequestBody::read(&mut req).await?;
+        req.extensions_mut().insert(Rc::new(body));
+    }
+    next.call(req).await
+}
+
+/// Turns the body into JSON, even if the request was multipart. The fields are parsed as JSON if
+/// they can be (as telegram does it), and the attachments are put under their field names
+fn raw_body_to_json(body: &RawRequestBody) -> Value {
+    match body {
+        RawRequestBody::Json(value) => value.clone(),
+        RawRequestBody::Multipart {
+            fields,
+            attachments,
+        } => {
+            let mut object = Map::new();
+            for (name, value) in fields {
+                let value =
+                    serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.clone()));
+                object.insert(name.clone(), value);
+            }
+            for attachment in attachments.values() {
+                let name = attachment.raw_name.clone();
+                object.insert(name, serde_json::to_value(attachment).unwrap_or_default());
+            }
+            Value::Object(object)
+        }
+        RawRequestBody::Other(text) if text.is_empty() => Value::Object(Map::new()),
+        RawRequestBody::Other(text) => Value::String(text.clone()),
+    }
+}
+
+/// Telegram doesn't care about the case of the first letter of the method, so `sendMessage` is the
+/// same as `SendMessage`
+pub(crate) fn normalize_method(method: &str) -> String {
+    let mut chars = method.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_ascii_uppercase().to_string() + chars.as_str()
+    })
+}
+
+/// Teloxide gives random names to the attachments, so they are replaced with the file names in
+/// the recorded body. That way the same requests are recorded the same way in every run
+fn with_stable_attachment_names(body: RawRequestBody) -> RawRequestBody {
+    let RawRequestBody::Multipart {
+        mut fields,
+        attachments,
+    } = body
+    else {
+        return body;
+    };
+    let attachments = attachments
+        .into_iter()
+        .map(|(file_name, mut attachment)| {
+            let old = format!("attach://{}", attachment.raw_name);
+            if fields.values().any(|value| value.contains(&old)) {
+                let new = format!("attach://{file_name}");
+                for value in fields.values_mut() {
+                    *value = value.replace(&old, &new);
+                }
+                attachment.raw_name = file_name.clone();
+            }
+            (file_name, attachment)
+        })
+        .collect();
+    RawRequestBody::Multipart {
+        fields,
+        attachments,
+    }
+}
+
+/// Records the request and the response in [`Responses::raw_requests`], if it is a request to the
+/// bot API
+async fn record_raw_request(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(method) = method_name(&req).filter(|method| method != "GetUpdates") else {
+        return next.call(req).await;
+    };
+    let Some(request_body) = request_body(&req) else {
+        return next.call(req).await;
+    };
+    let state = req.app_data::<Data<Mutex<State>>>().cloned();
+    let body = with_stable_attachment_names(request_body.raw.clone());
+
+    let response = next.call(req).await?;
+    let status = response.status().as_u16();
+    let (request, response) = response.into_parts();
+    let (response, response_body) = response.into_parts();
+    // The body fails only if the connection has to be dropped, so it is failed again after recording
+    let (response_body, dropped) = match body::to_bytes(response_body).await {
+        Ok(response_body) => (response_body, None),
+        Err(err) => (Bytes::new(), Some(err.to_string())),
+    };
+
+    if let Some(state) = state {
+        state
+            .lock()
+            .unwrap()
+            .responses
+            .raw_requests
+            .push(RawRequest {
+                method,
+                body,
+                status,
+                response: String::from_utf8_lossy(&response_body).to_string(),
+            });
+    }
+
+    let response = match dropped {
+        Some(reason) => response.set_body(dropped_connection_body(reason)),
+        None => response.set_body(BoxBody::new(response_body)),
+    };
+    Ok(ServiceResponse::new(request, response))
+}
+
+/// A body that fails makes actix close the connection in the middle of the response
+pub(crate) fn dropped_connection_body(reason: String) -> BoxBody {
+    let body = stream::once(async {
+        Err::<Bytes, _>(io::Error::new(io::ErrorKind::ConnectionAborted, reason))
+    });
+    BoxBody::new(BodyStream::new(body))
+}
+
 fn set_routes(cfg: &mut ServiceConfig) {
     cfg.route("/file/bot{token}/{file_name}", get().to(download_file))
+        .service(scope("/admin").configure(set_admin_routes))
         .service(scope("/bot{token}").configure(set_bot_routes));
 }
 
+fn set_admin_routes(cfg: &mut ServiceConfig) {
+    cfg.route("/updates", post().to(admin::add_updates))
+        .route("/responses", get().to(admin::get_responses))
+        .route("/messages", get().to(admin::get_messages))
+        .route("/reset", post().to(admin::reset));
+}
+
 fn set_bot_routes(cfg: &mut ServiceConfig) {
     cfg.route("/GetFile", post().to(get_file))
         .route("/SendMessage", post().to(send_message))
         .route("/GetWebhookInfo", post().to(get_webhook_info))
+        .route("/SetWebhook", post().to(set_webhook))
+        .route("/DeleteWebhook", post().to(delete_webhook))
         .route("/GetMe", post().to(get_me))
         .route("/GetUpdates", post().to(get_updates))
         .route("/SendPhoto", post().to(send_photo))
@@ -0,0 +1,800 @@
+use std::cmp::Reverse;
+
+use teloxide::{
+    types::{CustomEmojiId, MessageEntity, MessageEntityKind, ParseMode},
+    ApiError,
+};
+use url::Url;
+
+use super::routes::BotApiError;
+
+/// Turns the text with markup into the plain text and its entities, the way telegram does it.
+/// Returns the reason if the markup can't be parsed
+pub fn parse_text(
+    text: &str,
+    parse_mode: ParseMode,
+) -> Result<(String, Vec<MessageEntity>), String> {
+    #[allow(deprecated)]
+    let parsed = match parse_mode {
+        ParseMode::Html => parse_html(text)?,
+        ParseMode::Markdown => parse_markdown(text)?,
+        ParseMode::MarkdownV2 => parse_markdown_v2(text)?,
+    };
+    Ok(parsed.finish())
+}
+
+/// The text and the entities of a message. The explicit entities win over the `parse_mode`, like
+/// in telegram
+pub(crate) fn render_text(
+    text: &str,
+    parse_mode: Option<ParseMode>,
+    entities: Option<Vec<MessageEntity>>,
+) -> Result<(String, Vec<MessageEntity>), BotApiError> {
+    match (parse_mode, entities) {
+        (_, Some(entities)) => Ok((text.to_string(), entities)),
+        (None, None) => Ok((text.to_string(), vec![])),
+        (Some(parse_mode), None) => parse_text(text, parse_mode).map_err(|reason| {
+            BotApiError::new(ApiError::CantParseEntities(format!(
+                "Bad Request: can't parse entities: {reason}"
+            )))
+        }),
+    }
+}
+
+/// Same as [`render_text`], but for the optional texts, like captions
+pub(crate) fn render_optional_text(
+    text: Option<&str>,
+    parse_mode: Option<ParseMode>,
+    entities: Option<Vec<MessageEntity>>,
+) -> Result<(Option<String>, Vec<MessageEntity>), BotApiError> {
+    match text {
+        Some(text) => {
+            let (text, entities) = render_text(text, parse_mode, entities)?;
+            Ok((Some(text), entities))
+        }
+        None => Ok((None, vec![])),
+    }
+}
+
+/// The plain text that is being built, with the entities in it
+#[derive(Default)]
+struct Parsed {
+    text: String,
+    /// The length of the text in UTF-16 code units, which telegram counts the offsets in
+    utf16_len: usize,
+    /// The entities in the order they were opened. The unfinished ones are `None`
+    entities: Vec<Option<MessageEntity>>,
+}
+
+impl Parsed {
+    fn push(&mut self, c: char) {
+        self.text.push(c);
+        self.utf16_len += c.len_utf16();
+    }
+
+    fn push_str(&mut self, s: &str) {
+        s.chars().for_each(|c| self.push(c));
+    }
+
+    /// Reserves the place of the entity, so that the entities are sorted by the opening order
+    fn open(&mut self) -> usize {
+        self.entities.push(None);
+        self.entities.len() - 1
+    }
+
+    /// Finishes the entity that was opened at the `offset`
+    fn close(&mut self, index: usize, kind: MessageEntityKind, offset: usize) {
+        self.close_at(index, kind, offset, self.utf16_len);
+    }
+
+    /// Finishes the entity at the `end` instead of the current end of the text. Empty entities
+    /// are dropped
+    fn close_at(&mut self, index: usize, kind: MessageEntityKind, offset: usize, end: usize) {
+        if end > offset {
+            self.entities[index] = Some(MessageEntity::new(kind, offset, end - offset));
+        }
+    }
+
+    fn finish(self) -> (String, Vec<MessageEntity>) {
+        let mut entities: Vec<MessageEntity> = self.entities.into_iter().flatten().collect();
+        entities.sort_by_key(|entity| (entity.offset, Reverse(entity.length)));
+        (self.text, entities)
+    }
+}
+
+/// Telegram quietly drops the links with invalid urls
+fn text_link(url: &str) -> Option<MessageEntityKind> {
+    let url = Url::parse(url.trim()).ok()?;
+    Some(MessageEntityKind::TextLink { url })
+}
+
+fn custom_emoji(url: &str) -> Option<MessageEntityKind> {
+    let id = url.strip_prefix("tg://emoji?id=")?;
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(MessageEntityKind::CustomEmoji {
+        custom_emoji_id: CustomEmojiId(id.to_string()),
+    })
+}
+
+/// Decodes the HTML entities that telegram supports: the numeric ones, `&lt;`, `&gt;`, `&amp;`
+/// and `&quot;`. Everything else is left as it is
+fn decode_html_entity(text: &str) -> Option<(char, usize)> {
+    let end = text.find(';')?;
+    let name = &text[1..end];
+    let c = match name {
+        "lt" => '<',
+        "gt" => '>',
+        "amp" => '&',
+        "quot" => '"',
+        _ => {
+            let code =
+                if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                    u32::from_str_radix(hex, 16).ok()?
+                } else {
+                    name.strip_prefix('#')?.parse().ok()?
+                };
+            char::from_u32(code)?
+        }
+    };
+    Some((c, end + 1))
+}
+
+fn decode_html(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        let entity = if c == '&' {
+            decode_html_entity(&text[i..])
+        } else {
+            None
+        };
+        match entity {
+            Some((c, len)) => {
+                decoded.push(c);
+                i += len;
+            }
+            None => {
+                decoded.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+    decoded
+}
+
+/// Parses the attributes of a tag, like `href="url" class=name expandable`
+fn parse_html_attributes(mut text: &str) -> Vec<(String, String)> {
+    let mut attributes = vec![];
+    loop {
+        text = text.trim_start();
+        let name_end = text
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(text.len());
+        if name_end == 0 {
+            return attributes;
+        }
+        let name = text[..name_end].to_lowercase();
+        text = text[name_end..].trim_start();
+        let Some(rest) = text.strip_prefix('=') else {
+            attributes.push((name, String::new()));
+            continue;
+        };
+        text = rest.trim_start();
+        let value = match text.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let end = text[1..].find(quote).map_or(text.len(), |end| end + 1);
+                let value = &text[1..end];
+                text = text.get(end + 1..).unwrap_or("");
+                value
+            }
+            _ => {
+                let end = text.find(char::is_whitespace).unwrap_or(text.len());
+                let value = &text[..end];
+                text = &text[end..];
+                value
+            }
+        };
+        attributes.push((name, decode_html(value)));
+    }
+}
+
+struct HtmlTag {
+    name: String,
+    /// The entity that is made when the tag is closed, if there is one
+    kind: Option<MessageEntityKind>,
+    index: usize,
+    offset: usize,
+    /// The class of the `code` tag, which sets the language of the surrounding `pre`
+    class: Option<String>,
+}
+
+fn parse_html(text: &str) -> Result<Parsed, String> {
+    let mut parsed = Parsed::default();
+    let mut stack: Vec<HtmlTag> = vec![];
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        if c == '&' {
+            if let Some((decoded, len)) = decode_html_entity(&text[i..]) {
+                parsed.push(decoded);
+                i += len;
+                continue;
+            }
+        }
+        if c != '<' {
+            parsed.push(c);
+            i += c.len_utf8();
+            continue;
+        }
+
+        let Some(tag_len) = text[i..].find('>') else {
+            return Err(format!("Unclosed start tag at byte offset {i}"));
+        };
+        let tag = &text[i + 1..i + tag_len];
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim().to_lowercase();
+            let Some(open) = stack.pop() else {
+                return Err(format!("Unexpected end tag at byte offset {i}"));
+            };
+            if open.name != name {
+                return Err(format!(
+                    "Unmatched end tag at byte offset {i}, expected \"</{}>\", found \"</{name}>\"",
+                    open.name
+                ));
+            }
+            let language = open
+                .class
+                .as_deref()
+                .and_then(|class| class.strip_prefix("language-"));
+            let parent_pre = stack
+                .last_mut()
+                .filter(|parent| parent.name == "pre" && parent.offset == open.offset);
+            match (language, parent_pre) {
+                // `<pre><code class="language-rust">` is a single pre entity with the language
+                (Some(language), Some(pre)) => {
+                    pre.kind = Some(MessageEntityKind::Pre {
+                        language: Some(language.to_string()),
+                    });
+                }
+                _ => {
+                    if let Some(kind) = open.kind {
+                        parsed.close(open.index, kind, open.offset);
+                    }
+                }
+            }
+        } else {
+            let name_end = tag
+                .find(|c: char| c.is_whitespace() || c == '/')
+                .unwrap_or(tag.len());
+            let name = tag[..name_end].to_lowercase();
+            let attributes = parse_html_attributes(&tag[name_end..]);
+            let attribute = |attribute: &str| {
+                attributes
+                    .iter()
+                    .find(|(name, _)| name == attribute)
+                    .map(|(_, value)| value.clone())
+            };
+            let kind = match name.as_str() {
+                "b" | "strong" => Some(MessageEntityKind::Bold),
+                "i" | "em" => Some(MessageEntityKind::Italic),
+                "u" | "ins" => Some(MessageEntityKind::Underline),
+                "s" | "strike" | "del" => Some(MessageEntityKind::Strikethrough),
+                "tg-spoiler" => Some(MessageEntityKind::Spoiler),
+                "span" => {
+                    if attribute("class").as_deref() != Some("tg-spoiler") {
+                        return Err(format!(
+                            "Tag \"span\" must have class \"tg-spoiler\" at byte offset {i}"
+                        ));
+                    }
+                    Some(MessageEntityKind::Spoiler)
+                }
+                "code" => Some(MessageEntityKind::Code),
+                "pre" => Some(MessageEntityKind::Pre { language: None }),
+                "blockquote" if attribute("expandable").is_some() => {
+                    Some(MessageEntityKind::ExpandableBlockquote)
+                }
+                "blockquote" => Some(MessageEntityKind::Blockquote),
+                "a" => attribute("href").as_deref().and_then(text_link),
+                "tg-emoji" => {
+                    let id = attribute("emoji-id").unwrap_or_default();
+                    Some(custom_emoji(&format!("tg://emoji?id={id}")).ok_or_else(|| {
+                        format!("Invalid custom emoji identifier specified at byte offset {i}")
+                    })?)
+                }
+                _ => {
+                    return Err(format!(
+                        "Unsupported start tag \"{name}\" at byte offset {i}"
+                    ))
+                }
+            };
+            stack.push(HtmlTag {
+                index: parsed.open(),
+                offset: parsed.utf16_len,
+                class: attribute("class").filter(|_| name == "code"),
+                name,
+                kind,
+            });
+        }
+        i += tag_len + 1;
+    }
+
+    if let Some(open) = stack.pop() {
+        return Err(format!(
+            "Can't find end tag corresponding to start tag \"{}\"",
+            open.name
+        ));
+    }
+    Ok(parsed)
+}
+
+/// The legacy Markdown, which has no nesting and only a few entities
+fn parse_markdown(text: &str) -> Result<Parsed, String> {
+    let mut parsed = Parsed::default();
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        let start = i;
+        i += c.len_utf8();
+        match c {
+            '\\' if text[i..].starts_with(['_', '*', '`', '[']) => {
+                parsed.push(text[i..].chars().next().unwrap());
+                i += 1;
+            }
+            '_' | '*' | '`' | '[' => {
+                let is_pre = c == '`' && text[start..].starts_with("```");
+                let end_delimiter = match c {
+                    '[' => "]",
+                    _ if is_pre => "```",
+                    _ => &text[start..i],
+                };
+                if is_pre {
+                    i = start + 3;
+                }
+                let Some(len) = text[i..].find(end_delimiter) else {
+                    return Err(format!(
+                        "Can't find end of the entity starting at byte offset {start}"
+                    ));
+                };
+                let mut content = &text[i..i + len];
+                i += len + end_delimiter.len();
+
+                let mut language = None;
+                if is_pre {
+                    if let Some((first_line, rest)) = content.split_once('\n') {
+                        if !first_line.is_empty() && !first_line.contains(char::is_whitespace) {
+                            language = Some(first_line.to_string());
+                        }
+                        if language.is_some() || first_line.is_empty() {
+                            content = rest;
+                        }
+                    }
+                }
+                let kind = match c {
+                    '_' => Some(MessageEntityKind::Italic),
+                    '*' => Some(MessageEntityKind::Bold),
+                    '`' if is_pre => Some(MessageEntityKind::Pre { language }),
+                    '`' => Some(MessageEntityKind::Code),
+                    _ => {
+                        // The link works without the url part, but then it is just the text
+                        let url = match text[i..].strip_prefix('(') {
+                            Some(rest) => match rest.find(')') {
+                                Some(end) => {
+                                    i += end + 2;
+                                    &rest[..end]
+                                }
+                                None => "",
+                            },
+                            None => "",
+                        };
+                        text_link(url)
+                    }
+                };
+
+                let index = parsed.open();
+                let offset = parsed.utf16_len;
+                parsed.push_str(content);
+                if let Some(kind) = kind {
+                    parsed.close(index, kind, offset);
+                }
+            }
+            _ => parsed.push(c),
+        }
+    }
+    Ok(parsed)
+}
+
+/// The characters that have to be escaped in MarkdownV2 outside of the entities
+const MARKDOWN_V2_RESERVED: &str = "_*[]()~`>#+-=|{}.!";
+
+#[derive(Clone, Copy, PartialEq)]
+enum MarkdownV2Entity {
+    Bold,
+    Italic,
+    Underline,
+    Strikethrough,
+    Spoiler,
+    Code,
+    Pre,
+    TextUrl,
+    CustomEmoji,
+}
+
+impl MarkdownV2Entity {
+    fn end(self) -> &'static str {
+        match self {
+            Self::Bold => "*",
+            Self::Italic => "_",
+            Self::Underline => "__",
+            Self::Strikethrough => "~",
+            Self::Spoiler => "||",
+            Self::Code => "`",
+            Self::Pre => "```",
+            Self::TextUrl | Self::CustomEmoji => "]",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Bold => "Bold",
+            Self::Italic => "Italic",
+            Self::Underline => "Underline",
+            Self::Strikethrough => "Strikethrough",
+            Self::Spoiler => "Spoiler",
+            Self::Code => "Code",
+            Self::Pre => "Pre",
+            Self::TextUrl => "TextUrl",
+            Self::CustomEmoji => "CustomEmoji",
+        }
+    }
+}
+
+struct MarkdownV2Open {
+    entity: MarkdownV2Entity,
+    index: usize,
+    offset: usize,
+    byte_offset: usize,
+    language: Option<String>,
+}
+
+struct Blockquote {
+    index: usize,
+    offset: usize,
+    /// Started with `**>`, and ends with `||` at the end of a line
+    expandable: bool,
+}
+
+impl Blockquote {
+    fn kind(&self) -> MessageEntityKind {
+        if self.expandable {
+            MessageEntityKind::ExpandableBlockquote
+        } else {
+            MessageEntityKind::Blockquote
+        }
+    }
+}
+
+fn parse_markdown_v2(text: &str) -> Result<Parsed, String> {
+    let mut parsed = Parsed::default();
+    let mut stack: Vec<MarkdownV2Open> = vec![];
+    let mut blockquote: Option<Blockquote> = None;
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        let in_code = stack.last().is_some_and(|open| {
+            matches!(open.entity, MarkdownV2Entity::Code | MarkdownV2Entity::Pre)
+        });
+
+        // Any character up to 126 can be escaped, even inside of the code
+        if c == '\\' {
+            if let Some(escaped) = text[i + 1..]
+                .chars()
+                .next()
+                .filter(|c| (1..=126).contains(&(*c as u32)))
+            {
+                parsed.push(escaped);
+                i += 2;
+                continue;
+            }
+        }
+
+        let at_line_start = i == 0 || text[..i].ends_with('\n');
+        // The blockquote goes on while the lines start with `>`
+        if at_line_start && !in_code {
+            if blockquote.is_some() && c != '>' {
+                let open = blockquote.take().unwrap();
+                // The newline before the unquoted line isn't a part of the quote
+                let end = parsed.utf16_len - 1;
+                parsed.close_at(open.index, open.kind(), open.offset, end);
+            }
+            if blockquote.is_none() && (c == '>' || text[i..].starts_with("**>")) {
+                let expandable = c == '*';
+                blockquote = Some(Blockquote {
+                    index: parsed.open(),
+                    offset: parsed.utf16_len,
+                    expandable,
+                });
+                i += if expandable { 3 } else { 1 };
+                continue;
+            }
+            if c == '>' {
+                i += 1;
+                continue;
+            }
+        }
+
+        // `||` at the end of a line closes the expandable blockquote
+        if !in_code
+            && text[i..].starts_with("||")
+            && matches!(text[i + 2..].chars().next(), None | Some('\n'))
+            && blockquote.as_ref().is_some_and(|open| open.expandable)
+            && stack
+                .last()
+                .map_or(true, |open| open.entity != MarkdownV2Entity::Spoiler)
+        {
+            let open = blockquote.take().unwrap();
+            parsed.close(open.index, open.kind(), open.offset);
+            i += 2;
+            continue;
+        }
+
+        let closes = stack.last().is_some_and(|open| {
+            let end = open.entity.end();
+            text[i..].starts_with(end)
+                // `__` is an underline, not the end of the italic
+                && !(open.entity == MarkdownV2Entity::Italic && text[i..].starts_with("__"))
+        });
+        if closes {
+            let open = stack.pop().unwrap();
+            i += open.entity.end().len();
+            let kind = match open.entity {
+                MarkdownV2Entity::Bold => Some(MessageEntityKind::Bold),
+                MarkdownV2Entity::Italic => Some(MessageEntityKind::Italic),
+                MarkdownV2Entity::Underline => Some(MessageEntityKind::Underline),
+                MarkdownV2Entity::Strikethrough => Some(MessageEntityKind::Strikethrough),
+                MarkdownV2Entity::Spoiler => Some(MessageEntityKind::Spoiler),
+                MarkdownV2Entity::Code => Some(MessageEntityKind::Code),
+                MarkdownV2Entity::Pre => Some(MessageEntityKind::Pre {
+                    language: open.language,
+                }),
+                MarkdownV2Entity::TextUrl | MarkdownV2Entity::CustomEmoji => {
+                    let url = if text[i..].starts_with('(') {
+                        let (url, len) = parse_markdown_v2_url(&text[i + 1..])
+                            .ok_or_else(|| format!("Can't find end of a url at byte offset {i}"))?;
+                        i += len + 1;
+                        url
+                    } else {
+                        // Without the url part, the text itself is the url
+                        let start = open.offset;
+                        String::from_utf16_lossy(
+                            &parsed.text.encode_utf16().collect::<Vec<_>>()[start..],
+                        )
+                    };
+                    if open.entity == MarkdownV2Entity::CustomEmoji {
+                        Some(custom_emoji(&url).ok_or_else(|| {
+                            "Custom emoji entity must contain a tg://emoji URL".to_string()
+                        })?)
+                    } else {
+                        text_link(&url)
+                    }
+                }
+            };
+            if let Some(kind) = kind {
+                parsed.close(open.index, kind, open.offset);
+            }
+            continue;
+        }
+
+        if in_code || !MARKDOWN_V2_RESERVED.contains(c) {
+            parsed.push(c);
+            i += c.len_utf8();
+            continue;
+        }
+
+        let byte_offset = i;
+        let (entity, len) = match c {
+            '_' if text[i..].starts_with("__") => (MarkdownV2Entity::Underline, 2),
+            '_' => (MarkdownV2Entity::Italic, 1),
+            '*' => (MarkdownV2Entity::Bold, 1),
+            '~' => (MarkdownV2Entity::Strikethrough, 1),
+            '|' if text[i..].starts_with("||") => (MarkdownV2Entity::Spoiler, 2),
+            '[' => (MarkdownV2Entity::TextUrl, 1),
+            '!' if text[i..].starts_with("![") => (MarkdownV2Entity::CustomEmoji, 2),
+            '`' if text[i..].starts_with("```") => (MarkdownV2Entity::Pre, 3),
+            '`' => (MarkdownV2Entity::Code, 1),
+            _ => {
+                return Err(format!(
+                    "Character '{c}' is reserved and must be escaped with the preceding '\\'"
+                ))
+            }
+        };
+        i += len;
+
+        let mut language = None;
+        if entity == MarkdownV2Entity::Pre {
+            // The first line of the pre is its language, if there is nothing else on it
+            if let Some(line_end) = text[i..].find('\n') {
+                let first_line = &text[i..i + line_end];
+                if !first_line.contains(|c: char| c.is_whitespace() || c == '`') {
+                    if !first_line.is_empty() {
+                        language = Some(first_line.to_string());
+                    }
+                    i += line_end + 1;
+                }
+            }
+        }
+        stack.push(MarkdownV2Open {
+            entity,
+            index: parsed.open(),
+            offset: parsed.utf16_len,
+            byte_offset,
+            language,
+        });
+    }
+
+    if let Some(open) = stack.pop() {
+        return Err(format!(
+            "Can't find end of {} entity at byte offset {}",
+            open.entity.name(),
+            open.byte_offset
+        ));
+    }
+    if let Some(open) = blockquote {
+        parsed.close(open.index, open.kind(), open.offset);
+    }
+    Ok(parsed)
+}
+
+/// Reads the url until the closing `)`, where only `)` and `\` are escaped. Returns the url and
+/// its length with the `)`
+fn parse_markdown_v2_url(text: &str) -> Option<(String, usize)> {
+    let mut url = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            ')' => return Some((url, i + 1)),
+            '\\' => url.push(chars.next()?.1),
+            c => url.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> MessageEntityKind {
+        MessageEntityKind::TextLink {
+            url: Url::parse(url).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_html() {
+        let (text, entities) = parse_text(
+            "<b>bold <i>both</i></b> &lt;tag&gt; <a href=\"https://example.com\">link</a>",
+            ParseMode::Html,
+        )
+        .unwrap();
+        assert_eq!(text, "bold both <tag> link");
+        assert_eq!(
+            entities,
+            vec![
+                MessageEntity::bold(0, 9),
+                MessageEntity::italic(5, 4),
+                MessageEntity::new(url("https://example.com"), 16, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_html_pre_with_language() {
+        let (text, entities) = parse_text(
+            "<pre><code class=\"language-rust\">fn main() {}</code></pre>",
+            ParseMode::Html,
+        )
+        .unwrap();
+        assert_eq!(text, "fn main() {}");
+        assert_eq!(
+            entities,
+            vec![MessageEntity::pre(Some("rust".to_string()), 0, 12)]
+        );
+    }
+
+    #[test]
+    fn test_html_errors() {
+        assert_eq!(
+            parse_text("<b>bold", ParseMode::Html).unwrap_err(),
+            "Can't find end tag corresponding to start tag \"b\""
+        );
+        assert_eq!(
+            parse_text("<b>bold</i>", ParseMode::Html).unwrap_err(),
+            "Unmatched end tag at byte offset 7, expected \"</b>\", found \"</i>\""
+        );
+        assert_eq!(
+            parse_text("<marquee>hi</marquee>", ParseMode::Html).unwrap_err(),
+            "Unsupported start tag \"marquee\" at byte offset 0"
+        );
+    }
+
+    #[test]
+    fn test_utf16_offsets() {
+        // The emoji takes two UTF-16 code units
+        let (text, entities) = parse_text("🦀 <b>crab</b>", ParseMode::Html).unwrap();
+        assert_eq!(text, "🦀 crab");
+        assert_eq!(entities, vec![MessageEntity::bold(3, 4)]);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_markdown() {
+        let (text, entities) = parse_text(
+            "*bold* _italic_ `code` [link](https://example.com) \\*",
+            ParseMode::Markdown,
+        )
+        .unwrap();
+        assert_eq!(text, "bold italic code link *");
+        assert_eq!(
+            entities,
+            vec![
+                MessageEntity::bold(0, 4),
+                MessageEntity::italic(5, 6),
+                MessageEntity::code(12, 4),
+                MessageEntity::new(url("https://example.com"), 17, 4),
+            ]
+        );
+        assert_eq!(
+            parse_text("*bold", ParseMode::Markdown).unwrap_err(),
+            "Can't find end of the entity starting at byte offset 0"
+        );
+    }
+
+    #[test]
+    fn test_markdown_v2() {
+        let (text, entities) = parse_text(
+            "*bold _both_* __under__ ~strike~ ||spoiler|| [link](https://example.com/\\)) \\.",
+            ParseMode::MarkdownV2,
+        )
+        .unwrap();
+        assert_eq!(text, "bold both under strike spoiler link .");
+        assert_eq!(
+            entities,
+            vec![
+                MessageEntity::bold(0, 9),
+                MessageEntity::italic(5, 4),
+                MessageEntity::underline(10, 5),
+                MessageEntity::strikethrough(16, 6),
+                MessageEntity::spoiler(23, 7),
+                MessageEntity::new(url("https://example.com/)"), 31, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_markdown_v2_pre_and_blockquote() {
+        let (text, entities) = parse_text(
+            "```rust\nlet a = 1;```\n>quoted\n>lines\nafter",
+            ParseMode::MarkdownV2,
+        )
+        .unwrap();
+        assert_eq!(text, "let a = 1;\nquoted\nlines\nafter");
+        assert_eq!(
+            entities,
+            vec![
+                MessageEntity::pre(Some("rust".to_string()), 0, 10),
+                MessageEntity::new(MessageEntityKind::Blockquote, 11, 12),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_markdown_v2_errors() {
+        assert_eq!(
+            parse_text("Hello!", ParseMode::MarkdownV2).unwrap_err(),
+            "Character '!' is reserved and must be escaped with the preceding '\\'"
+        );
+        assert_eq!(
+            parse_text("a *bold", ParseMode::MarkdownV2).unwrap_err(),
+            "Can't find end of Bold entity at byte offset 2"
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use actix_web::{
+    body::BoxBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::ResponseError,
+    middleware::Next,
+    web::Data,
+};
+use serde_json::Value;
+use teloxide::{
+    types::{
+        Administrator, ChatId, ChatMemberKind, ChatPermissions, Me, MediaKind, Message,
+        MessageCommon, MessageKind, Restricted, UserId,
+    },
+    ApiError,
+};
+
+use super::{
+    chats::as_i64,
+    method_name, request_body,
+    routes::{BodyChatId, BotApiError},
+};
+use crate::state::State;
+
+/// The methods that post a message to the chat
+const POSTING_METHODS: [&str; 2] = ["ForwardMessage", "CopyMessage"];
+
+/// The rights of the bot in the chats. The bot can do anything in the chats where its membership
+/// isn't set, so the bots that don't care about the rights work as they are
+#[derive(Debug, Default)]
+pub struct Permissions {
+    bot_members: HashMap<ChatId, ChatMemberKind>,
+}
+
+impl Permissions {
+    /// Sets what the bot is in the chat, with its rights
+    pub fn set_bot_member(&mut self, chat_id: ChatId, kind: ChatMemberKind) {
+        self.bot_members.insert(chat_id, kind);
+    }
+
+    pub fn bot_member(&self, chat_id: ChatId) -> Option<&ChatMemberKind> {
+        self.bot_members.get(&chat_id)
+    }
+
+    /// If the bot can delete the messages of the others in the chat
+    pub(crate) fn can_delete_messages(&self, chat_id: ChatId) -> bool {
+        self.bot_member(chat_id)
+            .map_or(true, ChatMemberKind::can_delete_messages)
+    }
+
+    fn can_pin_messages(&self, chat_id: ChatId, is_channel: bool) -> bool {
+        match self.bot_member(chat_id) {
+            None | Some(ChatMemberKind::Owner(_)) => true,
+            // Channels don't have the pin right, editing the messages covers it
+            Some(ChatMemberKind::Administrator(Administrator {
+                can_pin_messages,
+                can_edit_messages,
+                ..
+            })) => *can_pin_messages || (is_channel && *can_edit_messages),
+            Some(ChatMemberKind::Restricted(Restricted {
+                can_pin_messages, ..
+            })) => *can_pin_messages,
+            Some(_) => false,
+        }
+    }
+
+    fn can_restrict_members(&self, chat_id: ChatId) -> bool {
+        self.bot_member(chat_id)
+            .map_or(true, ChatMemberKind::can_restrict_members)
+    }
+
+    fn can_post_messages(&self, chat_id: ChatId) -> bool {
+        self.bot_member(chat_id)
+            .map_or(true, ChatMemberKind::can_post_messages)
+    }
+}
+
+/// Returns the error telegram gives if the bot doesn't have the rights for the request
+fn check(state: &State, method: &str, body: &Value, bot_id: UserId) -> Result<(), ApiError> {
+    let Some(chat_id) = as_i64(&body["chat_id"]) else {
+        return Ok(());
+    };
+    let chat = state.chats.chat(&BodyChatId::Id(chat_id));
+    // Bots aren't members of the private chats, and can do anything there
+    if chat.is_private() {
+        return Ok(());
+    }
+    let permissions = &state.permissions;
+    match method {
+        "RestrictChatMember" if as_i64(&body["user_id"]) == Some(bot_id.0 as i64) => {
+            Err(ApiError::CantRestrictSelf)
+        }
+        "BanChatMember" | "UnbanChatMember" | "RestrictChatMember"
+            if !permissions.can_restrict_members(chat.id) =>
+        {
+            Err(ApiError::NotEnoughRightsToRestrict)
+        }
+        "PinChatMessage" if !permissions.can_pin_messages(chat.id, chat.is_channel()) => {
+            Err(ApiError::NotEnoughRightsToPinMessage)
+        }
+        "UnpinChatMessage" | "UnpinAllChatMessages"
+            if !permissions.can_pin_messages(chat.id, chat.is_channel()) =>
+        {
+            Err(ApiError::NotEnoughRightsToManagePins)
+        }
+        _ if chat.is_channel() && is_posting(method) && !permissions.can_post_messages(chat.id) => {
+            Err(ApiError::NotEnoughRightsToPostMessages)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn is_posting(method: &str) -> bool {
+    (method.starts_with("Send") && method != "SendChatAction") || POSTING_METHODS.contains(&method)
+}
+
+/// If the user can send the message to the chat, with the permissions they were restricted to
+pub(crate) fn is_allowed(permissions: &ChatPermissions, message: &Message) -> bool {
+    let MessageKind::Common(MessageCommon { media_kind, .. }) = &message.kind else {
+        // Service messages are not sent by the users themselves
+        return true;
+    };
+    let needed = match media_kind {
+        MediaKind::Text(_)
+        | MediaKind::Contact(_)
+        | MediaKind::Location(_)
+        | MediaKind::Venue(_) => ChatPermissions::SEND_MESSAGES,
+        MediaKind::Photo(_) => ChatPermissions::SEND_PHOTOS,
+        MediaKind::Video(_) => ChatPermissions::SEND_VIDEOS,
+        MediaKind::Audio(_) => ChatPermissions::SEND_AUDIOS,
+        MediaKind::Document(_) => ChatPermissions::SEND_DOCUMENTS,
+        MediaKind::Voice(_) => ChatPermissions::SEND_VOICE_NOTES,
+        MediaKind::VideoNote(_) => ChatPermissions::SEND_VIDEO_NOTES,
+        MediaKind::Poll(_) | MediaKind::Checklist(_) => ChatPermissions::SEND_POLLS,
+        _ => ChatPermissions::SEND_OTHER_MESSAGES,
+    };
+    permissions.contains(needed)
+}
+
+/// Responds with the `NotEnoughRightsTo...` errors if the bot's membership in the chat doesn't
+/// allow the request
+pub(crate) async fn check_request(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let (Some(state), Some(me)) = (
+        req.app_data::<Data<Mutex<State>>>().cloned(),
+        req.app_data::<Data<Me>>().cloned(),
+    ) else {
+        return next.call(req).await;
+    };
+    let (Some(method), Some(body)) = (method_name(&req), request_body(&req)) else {
+        return next.call(req).await;
+    };
+
+    let checked = check(&state.lock().unwrap(), &method, &body.json, me.id);
+    match checked {
+        Ok(()) => next.call(req).await,
+        Err(error) => Ok(req.into_response(BotApiError::new(error).error_response())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use teloxide::types::Member;
+
+    use super::*;
+    use crate::dataset::{MockChannelChat, MockMessagePhoto, MockMessageText, MockSupergroupChat};
+
+    const BOT_ID: UserId = UserId(1);
+
+    fn administrator() -> Administrator {
+        Administrator {
+            custom_title: None,
+            is_anonymous: false,
+            can_be_edited: false,
+            can_manage_chat: true,
+            can_change_info: false,
+            can_post_messages: false,
+            can_edit_messages: false,
+            can_delete_messages: false,
+            can_post_stories: false,
+            can_edit_stories: false,
+            can_delete_stories: false,
+            can_manage_video_chats: false,
+            can_invite_users: false,
+            can_restrict_members: false,
+            can_pin_messages: false,
+            can_manage_topics: false,
+            can_promote_members: false,
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let mut state = State::default();
+        let group = MockSupergroupChat::new().id(-1001).build();
+        state.chats.add_chat(group.clone());
+        let ban = json!({"chat_id": -1001, "user_id": 5});
+        assert_eq!(check(&state, "BanChatMember", &ban, BOT_ID), Ok(()));
+
+        state.permissions.set_bot_member(
+            group.id,
+            ChatMemberKind::Member(Member { until_date: None }),
+        );
+        assert_eq!(
+            check(&state, "BanChatMember", &ban, BOT_ID),
+            Err(ApiError::NotEnoughRightsToRestrict)
+        );
+        assert_eq!(
+            check(&state, "PinChatMessage", &ban, BOT_ID),
+            Err(ApiError::NotEnoughRightsToPinMessage)
+        );
+        assert_eq!(
+            check(&state, "UnpinAllChatMessages", &ban, BOT_ID),
+            Err(ApiError::NotEnoughRightsToManagePins)
+        );
+        assert_eq!(check(&state, "SendMessage", &ban, BOT_ID), Ok(()));
+
+        let admin = Administrator {
+            can_restrict_members: true,
+            ..administrator()
+        };
+        state
+            .permissions
+            .set_bot_member(group.id, ChatMemberKind::Administrator(admin));
+        assert_eq!(check(&state, "BanChatMember", &ban, BOT_ID), Ok(()));
+        let restrict_self = json!({"chat_id": -1001, "user_id": BOT_ID.0});
+        assert_eq!(
+            check(&state, "RestrictChatMember", &restrict_self, BOT_ID),
+            Err(ApiError::CantRestrictSelf)
+        );
+    }
+
+    #[test]
+    fn test_check_channel_posts() {
+        let mut state = State::default();
+        let channel = MockChannelChat::new().id(-1002).build();
+        state.chats.add_chat(channel.clone());
+        state
+            .permissions
+            .set_bot_member(channel.id, ChatMemberKind::Administrator(administrator()));
+        let body = json!({"chat_id": "-1002"});
+
+        assert_eq!(
+            check(&state, "SendMessage", &body, BOT_ID),
+            Err(ApiError::NotEnoughRightsToPostMessages)
+        );
+        assert_eq!(
+            check(&state, "CopyMessage", &body, BOT_ID),
+            Err(ApiError::NotEnoughRightsToPostMessages)
+        );
+        assert_eq!(check(&state, "SendChatAction", &body, BOT_ID), Ok(()));
+    }
+
+    #[test]
+    fn test_is_allowed() {
+        let text = MockMessageText::new().build();
+        let photo = MockMessagePhoto::new().build();
+        let only_text = ChatPermissions::SEND_MESSAGES;
+
+        assert!(is_allowed(&only_text, &text));
+        assert!(!is_allowed(&only_text, &photo));
+        assert!(!is_allowed(&ChatPermissions::empty(), &text));
+    }
+}
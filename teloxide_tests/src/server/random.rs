@@ -0,0 +1,66 @@
+use rand::{
+    distr::{Alphanumeric, SampleString},
+    rngs::StdRng,
+    Rng, SeedableRng,
+};
+use teloxide::types::{FileId, FileUniqueId, MediaGroupId};
+
+/// The source of everything random in the fake server. It can be seeded with
+/// [`MockBot::seed`], so that the responses are the same between the runs
+///
+/// [`MockBot::seed`]: crate::MockBot::seed
+pub struct Random(StdRng);
+
+impl Default for Random {
+    fn default() -> Self {
+        Self(StdRng::from_os_rng())
+    }
+}
+
+impl Random {
+    pub fn seed(&mut self, seed: u64) {
+        self.0 = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn file_id(&mut self) -> FileId {
+        FileId(Alphanumeric.sample_string(&mut self.0, 16))
+    }
+
+    pub fn file_unique_id(&mut self) -> FileUniqueId {
+        FileUniqueId(Alphanumeric.sample_string(&mut self.0, 8))
+    }
+
+    pub fn media_group_id(&mut self) -> MediaGroupId {
+        MediaGroupId(Alphanumeric.sample_string(&mut self.0, 16))
+    }
+
+    /// Random from 1 to 5 because it fits all the emoji
+    pub fn dice_value(&mut self) -> u8 {
+        self.0.random_range(1..=5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed() {
+        let mut first = Random::default();
+        let mut second = Random::default();
+        first.seed(42);
+        second.seed(42);
+        assert_eq!(first.file_id(), second.file_id());
+        assert_eq!(first.file_unique_id(), second.file_unique_id());
+        assert_eq!(first.media_group_id(), second.media_group_id());
+        assert_eq!(first.dice_value(), second.dice_value());
+    }
+
+    #[test]
+    fn test_dice_value() {
+        let mut random = Random::default();
+        for _ in 0..100 {
+            assert!((1..=5).contains(&random.dice_value()));
+        }
+    }
+}
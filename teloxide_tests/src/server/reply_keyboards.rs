@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+
+use teloxide::types::{
+    ButtonRequest, ChatId, ForceReply, KeyboardButton, KeyboardMarkup, Message, MessageEntityKind,
+    ReplyMarkup, UserId,
+};
+
+use super::chats::Chats;
+
+/// A value that is set either for everyone in the chat, or only for some of its users
+#[derive(Debug)]
+struct PerUser<T> {
+    chats: HashMap<ChatId, T>,
+    /// The users that got their own value, over the one of the chat. `None` means that it was
+    /// removed only for them
+    users: HashMap<(ChatId, UserId), Option<T>>,
+}
+
+impl<T> Default for PerUser<T> {
+    fn default() -> Self {
+        Self {
+            chats: HashMap::new(),
+            users: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Clone> PerUser<T> {
+    fn get(&self, chat_id: ChatId, user_id: UserId) -> Option<T> {
+        match self.users.get(&(chat_id, user_id)) {
+            Some(value) => value.clone(),
+            None => self.chats.get(&chat_id).cloned(),
+        }
+    }
+
+    /// Sets the value for the whole chat if `users` is `None`, or only for the `users` otherwise
+    fn set(&mut self, chat_id: ChatId, users: Option<&[UserId]>, value: Option<T>) {
+        let Some(users) = users else {
+            self.users.retain(|(chat, _), _| *chat != chat_id);
+            match value {
+                Some(value) => self.chats.insert(chat_id, value),
+                None => self.chats.remove(&chat_id),
+            };
+            return;
+        };
+        for user_id in users {
+            self.users.insert((chat_id, *user_id), value.clone());
+        }
+    }
+}
+
+/// The reply keyboards and the force replies that the bot has shown to the users. Unlike the
+/// inline keyboards, they aren't a part of the message, and stay until the bot replaces or removes
+/// them
+#[derive(Debug, Default)]
+pub struct ReplyKeyboards {
+    keyboards: PerUser<KeyboardMarkup>,
+    force_replies: PerUser<ForceReply>,
+}
+
+impl ReplyKeyboards {
+    /// The keyboard that the user sees in the chat instead of the regular one
+    pub fn keyboard(&self, chat_id: ChatId, user_id: UserId) -> Option<KeyboardMarkup> {
+        self.keyboards.get(chat_id, user_id)
+    }
+
+    /// The force reply that waits for the user to reply to the bot
+    pub fn force_reply(&self, chat_id: ChatId, user_id: UserId) -> Option<ForceReply> {
+        self.force_replies.get(chat_id, user_id)
+    }
+
+    /// Shows the reply markup of the message that the bot has just sent. The `chats` are needed to
+    /// find the users mentioned by their usernames
+    pub(crate) fn show(
+        &mut self,
+        message: &Message,
+        reply_markup: Option<&ReplyMarkup>,
+        chats: &Chats,
+    ) {
+        let chat_id = message.chat.id;
+        match reply_markup {
+            Some(ReplyMarkup::Keyboard(keyboard)) => {
+                let users = targets(message, keyboard.selective, chats);
+                self.keyboards
+                    .set(chat_id, users.as_deref(), Some(keyboard.clone()));
+            }
+            Some(ReplyMarkup::KeyboardRemove(remove)) => {
+                let users = targets(message, remove.selective, chats);
+                self.keyboards.set(chat_id, users.as_deref(), None);
+            }
+            Some(ReplyMarkup::ForceReply(force_reply)) => {
+                let users = targets(message, force_reply.selective, chats);
+                self.force_replies
+                    .set(chat_id, users.as_deref(), Some(force_reply.clone()));
+            }
+            Some(ReplyMarkup::InlineKeyboard(_)) | None => {}
+        }
+    }
+
+    /// The user answered in the chat. That is the reply the force reply waited for, and the one
+    /// time keyboard hides after its button is pressed
+    pub(crate) fn user_sent(&mut self, message: &Message) {
+        let Some(user) = message.from.as_ref() else {
+            return;
+        };
+        let (chat_id, user_id) = (message.chat.id, user.id);
+        self.force_replies.set(chat_id, Some(&[user_id]), None);
+
+        let Some(keyboard) = self.keyboard(chat_id, user_id) else {
+            return;
+        };
+        let pressed = keyboard
+            .keyboard
+            .iter()
+            .flatten()
+            .any(|button| is_sent_by(button, message));
+        if keyboard.one_time_keyboard && pressed {
+            self.keyboards.set(chat_id, Some(&[user_id]), None);
+        }
+    }
+}
+
+/// If the message is what the `button` sends when it is pressed. The plain buttons send their
+/// text, and the request buttons send what the user shared with them
+fn is_sent_by(button: &KeyboardButton, message: &Message) -> bool {
+    match &button.request {
+        None => message.text() == Some(button.text.as_str()),
+        Some(ButtonRequest::Contact) => message.contact().is_some(),
+        Some(ButtonRequest::Location) => message.location().is_some(),
+        Some(ButtonRequest::Poll(_)) => message.poll().is_some(),
+        Some(ButtonRequest::RequestUsers(request)) => message
+            .shared_users()
+            .is_some_and(|shared| shared.request_id == request.request_id),
+        Some(ButtonRequest::RequestChat(request)) => message
+            .shared_chat()
+            .is_some_and(|shared| shared.request_id == request.request_id),
+        Some(ButtonRequest::WebApp(_)) => message.web_app_data().is_some(),
+    }
+}
+
+/// The users that the selective markup is shown to: the ones mentioned in the message, and the
+/// sender of the message it replies to. `None` means everyone in the chat. The `@username`
+/// mentions only count for the users the fake server knows about
+fn targets(message: &Message, selective: bool, chats: &Chats) -> Option<Vec<UserId>> {
+    // There is only one user in a private chat anyway
+    if !selective || message.chat.is_private() {
+        return None;
+    }
+    let entities = message
+        .parse_entities()
+        .or_else(|| message.parse_caption_entities())
+        .unwrap_or_default();
+    let mentioned = entities.iter().filter_map(|entity| match entity.kind() {
+        MessageEntityKind::TextMention { user } => Some(user.id),
+        MessageEntityKind::Mention => chats.find_user(entity.text()).map(|user| user.id),
+        _ => None,
+    });
+    let replied_to = message
+        .reply_to_message()
+        .and_then(|reply| reply.from.as_ref())
+        .map(|user| user.id);
+    Some(mentioned.chain(replied_to).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::{MessageEntity, MessageKind};
+
+    use super::*;
+    use crate::{MockGroupChat, MockMessageContact, MockMessageText, MockUser};
+
+    fn keyboard() -> KeyboardMarkup {
+        KeyboardMarkup::new([[KeyboardButton::new("Yes"), KeyboardButton::new("No")]])
+    }
+
+    fn group_message() -> Message {
+        MockMessageText::new()
+            .chat(MockGroupChat::new().build())
+            .build()
+    }
+
+    #[test]
+    fn test_keyboard_for_everyone() {
+        let mut keyboards = ReplyKeyboards::default();
+        let message = group_message();
+        let chat_id = message.chat.id;
+        keyboards.show(
+            &message,
+            Some(&ReplyMarkup::Keyboard(keyboard())),
+            &Chats::default(),
+        );
+        assert_eq!(keyboards.keyboard(chat_id, UserId(1)), Some(keyboard()));
+        assert_eq!(keyboards.keyboard(chat_id, UserId(2)), Some(keyboard()));
+
+        keyboards.show(&message, Some(&ReplyMarkup::kb_remove()), &Chats::default());
+        assert_eq!(keyboards.keyboard(chat_id, UserId(1)), None);
+    }
+
+    #[test]
+    fn test_selective_keyboard() {
+        let mut keyboards = ReplyKeyboards::default();
+        let user = MockUser::new().id(42).build();
+        let reply_to = MockMessageText::new().from(user).build();
+        let mut message = group_message();
+        if let MessageKind::Common(common) = &mut message.kind {
+            common.reply_to_message = Some(Box::new(reply_to));
+        }
+        let chat_id = message.chat.id;
+
+        let selective = keyboard().selective();
+        keyboards.show(
+            &message,
+            Some(&ReplyMarkup::Keyboard(selective)),
+            &Chats::default(),
+        );
+        assert!(keyboards.keyboard(chat_id, UserId(42)).is_some());
+        assert_eq!(keyboards.keyboard(chat_id, UserId(1)), None);
+    }
+
+    #[test]
+    fn test_one_time_keyboard() {
+        let mut keyboards = ReplyKeyboards::default();
+        let message = group_message();
+        let chat_id = message.chat.id;
+        let one_time = keyboard().one_time_keyboard();
+        keyboards.show(
+            &message,
+            Some(&ReplyMarkup::Keyboard(one_time)),
+            &Chats::default(),
+        );
+
+        let user = MockUser::new().id(1).build();
+        let chat = message.chat.clone();
+        let typed = MockMessageText::new()
+            .text("Maybe")
+            .chat(chat.clone())
+            .from(user.clone());
+        keyboards.user_sent(&typed.build());
+        assert!(keyboards.keyboard(chat_id, UserId(1)).is_some());
+
+        let pressed = MockMessageText::new().text("Yes").chat(chat).from(user);
+        keyboards.user_sent(&pressed.build());
+        assert_eq!(keyboards.keyboard(chat_id, UserId(1)), None);
+        // The others still see it
+        assert!(keyboards.keyboard(chat_id, UserId(2)).is_some());
+    }
+
+    #[test]
+    fn test_selective_keyboard_for_username() {
+        let mut keyboards = ReplyKeyboards::default();
+        let mut chats = Chats::default();
+        chats.add_user(MockUser::new().id(42).username("Someone").build());
+        let text = "Hi @someone";
+        let message = MockMessageText::new()
+            .chat(MockGroupChat::new().build())
+            .text(text)
+            .entities(vec![MessageEntity::new(MessageEntityKind::Mention, 3, 8)])
+            .build();
+        let chat_id = message.chat.id;
+
+        let selective = ReplyMarkup::Keyboard(keyboard().selective());
+        keyboards.show(&message, Some(&selective), &chats);
+        assert!(keyboards.keyboard(chat_id, UserId(42)).is_some());
+        assert_eq!(keyboards.keyboard(chat_id, UserId(1)), None);
+    }
+
+    #[test]
+    fn test_one_time_request_keyboard() {
+        let mut keyboards = ReplyKeyboards::default();
+        let message = group_message();
+        let chat_id = message.chat.id;
+        let one_time =
+            KeyboardMarkup::new([[KeyboardButton::new("Share").request(ButtonRequest::Contact)]])
+                .one_time_keyboard();
+        keyboards.show(
+            &message,
+            Some(&ReplyMarkup::Keyboard(one_time)),
+            &Chats::default(),
+        );
+
+        // The contact doesn't have the text of the button, but it is what the button sends
+        let user = MockUser::new().id(1).build();
+        let contact = MockMessageContact::new().chat(message.chat).from(user);
+        keyboards.user_sent(&contact.build());
+        assert_eq!(keyboards.keyboard(chat_id, UserId(1)), None);
+    }
+}
@@ -1,150 +1,251 @@
-use teloxide::types::{Message, MessageId};
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use teloxide::types::{Message, MessageId, Update};
 
 use super::routes::{
     answer_callback_query::*, ban_chat_member::*, copy_message::*, delete_message::*,
-    edit_message_caption::*, edit_message_reply_markup::*, edit_message_text::*,
+    delete_webhook::*, edit_message_caption::*, edit_message_reply_markup::*, edit_message_text::*,
     forward_message::*, pin_chat_message::*, restrict_chat_member::*, send_animation::*,
     send_audio::*, send_chat_action::*, send_contact::*, send_dice::*, send_document::*,
     send_invoice::*, send_location::*, send_media_group::*, send_message::*, send_photo::*,
     send_poll::*, send_sticker::*, send_venue::*, send_video::*, send_video_note::*, send_voice::*,
-    set_message_reaction::*, set_my_commands::*, unban_chat_member::*, unpin_all_chat_messages::*,
-    unpin_chat_message::*,
+    set_message_reaction::*, set_my_commands::*, set_webhook::*, unban_chat_member::*,
+    unpin_all_chat_messages::*, unpin_chat_message::*, Attachment,
 };
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessageText {
     // For better syntax, this is a struct, not a tuple
     pub message: Message,
     pub bot_request: SendMessageTextBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessagePhoto {
     pub message: Message,
     pub bot_request: SendMessagePhotoBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessageVideo {
     pub message: Message,
     pub bot_request: SendMessageVideoBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessageAudio {
     pub message: Message,
     pub bot_request: SendMessageAudioBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessageVoice {
     pub message: Message,
     pub bot_request: SendMessageVoiceBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessageVideoNote {
     pub message: Message,
     pub bot_request: SendMessageVideoNoteBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessageDocument {
     pub message: Message,
     pub bot_request: SendMessageDocumentBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessageAnimation {
     pub message: Message,
     pub bot_request: SendMessageAnimationBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessageLocation {
     pub message: Message,
     pub bot_request: SendMessageLocationBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessageVenue {
     pub message: Message,
     pub bot_request: SendMessageVenueBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessageContact {
     pub message: Message,
     pub bot_request: SendMessageContactBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessageDice {
     pub message: Message,
     pub bot_request: SendMessageDiceBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessagePoll {
     pub message: Message,
     pub bot_request: SendMessagePollBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessageSticker {
     pub message: Message,
     pub bot_request: SendMessageStickerBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMediaGroup {
     pub messages: Vec<Message>,
     pub bot_request: SendMediaGroupBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct SentMessageInvoice {
     pub message: Message,
     pub bot_request: SendMessageInvoiceBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct EditedMessageText {
     pub message: Message,
     pub bot_request: EditMessageTextBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct EditedMessageCaption {
     pub message: Message,
     pub bot_request: EditMessageCaptionBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct DeletedMessage {
     pub message: Message,
     pub bot_request: DeleteMessageBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct EditedMessageReplyMarkup {
     pub message: Message,
     pub bot_request: EditMessageReplyMarkupBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct ForwardedMessage {
     pub message: Message,
     pub bot_request: ForwardMessageBody,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct CopiedMessage {
     pub message_id: MessageId,
     pub bot_request: CopyMessageBody,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Serialize)]
+pub struct DeliveredUpdate {
+    pub update: Update,
+    /// The HTTP status code that the webhook responded with
+    pub status: u16,
+}
+
+/// A single call to the fake server, with its place in [`Responses::timeline`]
+#[derive(Clone, Debug, Serialize)]
+pub struct BotCall {
+    /// The position of the call in the timeline, starting from 0
+    pub index: usize,
+    /// The time of the fake server when the call was made
+    pub date: DateTime<Utc>,
+    /// What the call was. It has the same thing that was put in the typed list of
+    /// [`Responses`] for that call
+    pub kind: BotCallKind,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub enum BotCallKind {
+    SentMessageText(SentMessageText),
+    SentMessagePhoto(SentMessagePhoto),
+    SentMessageVideo(SentMessageVideo),
+    SentMessageAudio(SentMessageAudio),
+    SentMessageVoice(SentMessageVoice),
+    SentMessageVideoNote(SentMessageVideoNote),
+    SentMessageDocument(SentMessageDocument),
+    SentMessageAnimation(SentMessageAnimation),
+    SentMessageLocation(SentMessageLocation),
+    SentMessageVenue(SentMessageVenue),
+    SentMessageContact(SentMessageContact),
+    SentMessageDice(SentMessageDice),
+    SentMessagePoll(SentMessagePoll),
+    SentMessageSticker(SentMessageSticker),
+    SentMediaGroup(SentMediaGroup),
+    SentMessageInvoice(SentMessageInvoice),
+    EditedMessageText(EditedMessageText),
+    EditedMessageCaption(EditedMessageCaption),
+    EditedMessageReplyMarkup(EditedMessageReplyMarkup),
+    DeletedMessage(DeletedMessage),
+    ForwardedMessage(ForwardedMessage),
+    CopiedMessage(CopiedMessage),
+    AnsweredCallbackQuery(AnswerCallbackQueryBody),
+    PinnedChatMessage(PinChatMessageBody),
+    UnpinnedChatMessage(UnpinChatMessageBody),
+    UnpinnedAllChatMessages(UnpinAllChatMessagesBody),
+    BannedChatMember(BanChatMemberBody),
+    UnbannedChatMember(UnbanChatMemberBody),
+    RestrictedChatMember(RestrictChatMemberBody),
+    SentChatAction(SendChatActionBody),
+    SetMessageReaction(SetMessageReactionBody),
+    SetMyCommands(SetMyCommandsBody),
+    SetWebhook(SetWebhookBody),
+    DeletedWebhook(DeleteWebhookBody),
+}
+
+/// A request that was refused by the flood control
+#[derive(Clone, Debug, Serialize)]
+pub struct ThrottledRequest {
+    /// The name of the method, like `SendMessage`
+    pub method: String,
+    /// The chat the bot tried to send the message to, if it was a number
+    pub chat_id: Option<i64>,
+    /// How many seconds the bot was told to wait
+    pub retry_after: u32,
+    /// The time of the fake server when the request was refused
+    pub date: DateTime<Utc>,
+}
+
+/// The body of a request, as the bot sent it
+#[derive(Clone, Debug, Serialize)]
+pub enum RawRequestBody {
+    Json(serde_json::Value),
+    Multipart {
+        fields: BTreeMap<String, String>,
+        /// The attached files by their file names
+        attachments: BTreeMap<String, Attachment>,
+    },
+    /// Anything that isn't JSON or multipart, like an empty body
+    Other(String),
+}
+
+/// A request to the bot API of the fake server, exactly as it came and left
+#[derive(Clone, Debug, Serialize)]
+pub struct RawRequest {
+    /// The name of the method, like `SendMessage`
+    pub method: String,
+    /// The body of the request. The random names teloxide gives to the attachments are replaced
+    /// with their file names, so the recorded requests are the same in every run
+    pub body: RawRequestBody,
+    /// The HTTP status code that the fake server responded with
+    pub status: u16,
+    /// The body of the response. It is empty if the connection was dropped
+    pub response: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
 pub struct Responses {
     /// All of the sent messages, including text, photo, audio, etc.
     /// Be warned, editing or deleting messages do not affect this list!
@@ -309,4 +410,85 @@ pub struct Responses {
     /// Telegram doesn't return anything, because there isn't anything to return, so there is no
     /// `.message` field.
     pub set_my_commands: Vec<SetMyCommandsBody>,
+
+    /// This has only the requests that were sent to the fake server to set the webhook.
+    /// Telegram doesn't return anything, because there isn't anything to return, so there is no
+    /// `.message` field.
+    pub set_webhooks: Vec<SetWebhookBody>,
+
+    /// This has only the requests that were sent to the fake server to delete the webhook.
+    /// Telegram doesn't return anything, because there isn't anything to return, so there is no
+    /// `.message` field.
+    pub deleted_webhooks: Vec<DeleteWebhookBody>,
+
+    /// This has the updates that the fake server sent to the webhook of the bot, in the order
+    /// they were sent, if the bot was dispatched with `dispatch_webhook`.
+    /// The `.status` field has the status code that the webhook responded with.
+    pub delivered_updates: Vec<DeliveredUpdate>,
+
+    /// All of the calls from above, in the order they were made. Unlike the typed lists, this
+    /// one can show that the bot sent a chat action, then edited a message, and only then
+    /// deleted another one. `sent_messages` and `delivered_updates` are not calls by themselves,
+    /// so they are not here
+    pub timeline: Vec<BotCall>,
+
+    /// Every request to the bot API, in the order they came in, including the ones to the
+    /// endpoints that aren't implemented yet, and the ones that failed. Long polling with
+    /// `getUpdates` is left out, it would drown out everything else
+    pub raw_requests: Vec<RawRequest>,
+
+    /// The requests that were refused with `RetryAfter`, if the flood control is enabled.
+    /// They don't show up anywhere else, except for `raw_requests`
+    pub throttled_requests: Vec<ThrottledRequest>,
+}
+
+impl Responses {
+    /// Puts the call in its typed list and at the end of the timeline
+    pub(crate) fn record(&mut self, kind: BotCallKind, date: DateTime<Utc>) {
+        match kind.clone() {
+            BotCallKind::SentMessageText(call) => self.sent_messages_text.push(call),
+            BotCallKind::SentMessagePhoto(call) => self.sent_messages_photo.push(call),
+            BotCallKind::SentMessageVideo(call) => self.sent_messages_video.push(call),
+            BotCallKind::SentMessageAudio(call) => self.sent_messages_audio.push(call),
+            BotCallKind::SentMessageVoice(call) => self.sent_messages_voice.push(call),
+            BotCallKind::SentMessageVideoNote(call) => self.sent_messages_video_note.push(call),
+            BotCallKind::SentMessageDocument(call) => self.sent_messages_document.push(call),
+            BotCallKind::SentMessageAnimation(call) => self.sent_messages_animation.push(call),
+            BotCallKind::SentMessageLocation(call) => self.sent_messages_location.push(call),
+            BotCallKind::SentMessageVenue(call) => self.sent_messages_venue.push(call),
+            BotCallKind::SentMessageContact(call) => self.sent_messages_contact.push(call),
+            BotCallKind::SentMessageDice(call) => self.sent_messages_dice.push(call),
+            BotCallKind::SentMessagePoll(call) => self.sent_messages_poll.push(call),
+            BotCallKind::SentMessageSticker(call) => self.sent_messages_sticker.push(call),
+            BotCallKind::SentMediaGroup(call) => self.sent_media_group.push(call),
+            BotCallKind::SentMessageInvoice(call) => self.sent_messages_invoice.push(call),
+            BotCallKind::EditedMessageText(call) => self.edited_messages_text.push(call),
+            BotCallKind::EditedMessageCaption(call) => self.edited_messages_caption.push(call),
+            BotCallKind::EditedMessageReplyMarkup(call) => {
+                self.edited_messages_reply_markup.push(call)
+            }
+            BotCallKind::DeletedMessage(call) => self.deleted_messages.push(call),
+            BotCallKind::ForwardedMessage(call) => self.forwarded_messages.push(call),
+            BotCallKind::CopiedMessage(call) => self.copied_messages.push(call),
+            BotCallKind::AnsweredCallbackQuery(call) => self.answered_callback_queries.push(call),
+            BotCallKind::PinnedChatMessage(call) => self.pinned_chat_messages.push(call),
+            BotCallKind::UnpinnedChatMessage(call) => self.unpinned_chat_messages.push(call),
+            BotCallKind::UnpinnedAllChatMessages(call) => {
+                self.unpinned_all_chat_messages.push(call)
+            }
+            BotCallKind::BannedChatMember(call) => self.banned_chat_members.push(call),
+            BotCallKind::UnbannedChatMember(call) => self.unbanned_chat_members.push(call),
+            BotCallKind::RestrictedChatMember(call) => self.restricted_chat_members.push(call),
+            BotCallKind::SentChatAction(call) => self.sent_chat_actions.push(call),
+            BotCallKind::SetMessageReaction(call) => self.set_message_reaction.push(call),
+            BotCallKind::SetMyCommands(call) => self.set_my_commands.push(call),
+            BotCallKind::SetWebhook(call) => self.set_webhooks.push(call),
+            BotCallKind::DeletedWebhook(call) => self.deleted_webhooks.push(call),
+        }
+        self.timeline.push(BotCall {
+            index: self.timeline.len(),
+            date,
+            kind,
+        });
+    }
 }
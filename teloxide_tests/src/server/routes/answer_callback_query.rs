@@ -1,12 +1,12 @@
 use std::sync::Mutex;
 
 use actix_web::{web, Responder};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use super::make_telegram_result;
-use crate::state::State;
+use crate::{server::BotCallKind, state::State};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AnswerCallbackQueryBody {
     pub callback_query_id: String,
     pub text: Option<String>,
@@ -20,8 +20,6 @@ pub async fn answer_callback_query(
     body: web::Json<AnswerCallbackQueryBody>,
 ) -> impl Responder {
     let mut lock = state.lock().unwrap();
-    lock.responses
-        .answered_callback_queries
-        .push(body.into_inner());
+    lock.record(BotCallKind::AnsweredCallbackQuery(body.into_inner()));
     make_telegram_result(true)
 }
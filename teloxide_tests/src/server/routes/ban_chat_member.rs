@@ -1,12 +1,16 @@
 use std::sync::Mutex;
 
 use actix_web::{web, Responder};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use teloxide::types::{ChatId, UserId};
 
 use super::BodyChatId;
-use crate::{server::routes::make_telegram_result, state::State};
+use crate::{
+    server::{routes::make_telegram_result, BotCallKind},
+    state::State,
+};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BanChatMemberBody {
     pub chat_id: BodyChatId,
     pub user_id: u64,
@@ -21,16 +25,16 @@ pub async fn ban_chat_member(
     let mut lock = state.lock().unwrap();
     let chat_id = body.chat_id.id();
     if body.revoke_messages.is_some() && body.revoke_messages.unwrap() {
-        for message in lock.messages.messages.clone() {
-            if message.chat.id.0 == chat_id
-                && message.from.is_some()
-                && message.from.unwrap().id.0 == body.user_id
-            {
-                lock.messages.delete_message(message.id.0);
+        for message in lock.messages.chat_history(ChatId(chat_id)) {
+            if message.from.is_some() && message.from.unwrap().id.0 == body.user_id {
+                lock.messages.delete_message(ChatId(chat_id), message.id.0);
             }
         }
     }
-    lock.responses.banned_chat_members.push(body.into_inner());
+    let until = lock.clock.until(body.until_date);
+    lock.banned_users
+        .insert((ChatId(chat_id), UserId(body.user_id)), until);
+    lock.record(BotCallKind::BannedChatMember(body.into_inner()));
 
     make_telegram_result(true)
 }
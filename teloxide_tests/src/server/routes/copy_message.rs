@@ -1,20 +1,28 @@
 use std::sync::Mutex;
 
-use actix_web::{error::ErrorBadRequest, web, Responder};
-use serde::Deserialize;
+use actix_web::{web, Responder, ResponseError};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use teloxide::types::{
-    Me, MediaAnimation, MediaAudio, MediaDocument, MediaKind, MediaPhoto, MediaVideo, MediaVoice,
-    MessageEntity, MessageId, MessageKind, ParseMode, ReplyMarkup,
+use teloxide::{
+    types::{
+        ChatId, Me, MediaAnimation, MediaAudio, MediaDocument, MediaKind, MediaPhoto, MediaVideo,
+        MediaVoice, MessageEntity, MessageId, MessageKind, ParseMode, ReplyMarkup,
+    },
+    ApiError,
 };
 
-use super::{make_telegram_result, BodyChatId};
+use super::{check_can_be_copied, make_telegram_result, BodyChatId, BotApiError};
 use crate::{
-    server::{routes::check_if_message_exists, CopiedMessage},
+    server::{
+        limits::{caption_is_too_long, check_caption},
+        parse_mode::render_optional_text,
+        routes::check_if_message_exists,
+        BotCallKind, CopiedMessage,
+    },
     state::State,
 };
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CopyMessageBody {
     pub chat_id: BodyChatId,
     pub message_thread_id: Option<i64>,
@@ -35,11 +43,35 @@ pub async fn copy_message(
     state: web::Data<Mutex<State>>,
 ) -> impl Responder {
     let mut lock = state.lock().unwrap();
-    let chat = body.chat_id.chat();
-    check_if_message_exists!(lock, body.message_id);
-    let mut message = lock.messages.get_message(body.message_id).unwrap();
+    let chat = lock.chats.chat(&body.chat_id);
+    let from_chat_id = ChatId(body.from_chat_id.id());
+    check_if_message_exists!(
+        lock,
+        from_chat_id,
+        body.message_id,
+        ApiError::MessageToCopyNotFound
+    );
+    let (new_caption, new_caption_entities) = match render_optional_text(
+        body.caption.as_deref(),
+        body.parse_mode,
+        body.caption_entities.clone(),
+    ) {
+        Ok(rendered) => rendered,
+        Err(err) => return err.error_response(),
+    };
+    if let Err(err) = check_caption(new_caption.as_deref(), caption_is_too_long()) {
+        return err.error_response();
+    }
+    let mut message = lock
+        .messages
+        .get_message(from_chat_id, body.message_id)
+        .unwrap();
+    if let Err(err) = check_can_be_copied(&message) {
+        return BotApiError::new(err).error_response();
+    }
     message.chat = chat;
     message.from = Some(me.user.clone());
+    message.date = lock.clock.now();
 
     // FIXME: Use show_caption_above_media
     if let MessageKind::Common(ref mut common) = message.kind {
@@ -76,8 +108,8 @@ pub async fn copy_message(
                 ref mut caption_entities,
                 ..
             }) => {
-                *caption = body.caption.clone();
-                *caption_entities = body.caption_entities.clone().unwrap_or_default();
+                *caption = new_caption;
+                *caption_entities = new_caption_entities;
             }
             _ => {}
         };
@@ -87,16 +119,16 @@ pub async fn copy_message(
         common.has_protected_content = body.protect_content.unwrap_or(false);
     }
 
-    let last_id = lock.messages.max_message_id();
+    let last_id = lock.messages.max_message_id(message.chat.id);
     message.id = MessageId(last_id + 1);
-    message.chat = body.chat_id.chat();
     let message = lock.messages.add_message(message);
 
     lock.responses.sent_messages.push(message.clone());
-    lock.responses.copied_messages.push(CopiedMessage {
+    lock.show_reply_markup(&message, body.reply_markup.as_ref());
+    lock.record(BotCallKind::CopiedMessage(CopiedMessage {
         message_id: message.id,
         bot_request: body.into_inner(),
-    });
+    }));
 
     make_telegram_result(json!({
         "message_id": message.id.0
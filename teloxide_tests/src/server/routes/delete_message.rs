@@ -1,15 +1,19 @@
 use std::sync::Mutex;
 
-use actix_web::{error::ErrorBadRequest, web, Responder};
-use serde::Deserialize;
+use actix_web::{web, Responder, ResponseError};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::{ChatId, Me},
+    ApiError,
+};
 
-use super::{check_if_message_exists, BodyChatId};
+use super::{check_if_message_exists, BodyChatId, BotApiError};
 use crate::{
-    server::{routes::make_telegram_result, DeletedMessage},
+    server::{routes::make_telegram_result, BotCallKind, DeletedMessage},
     state::State,
 };
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DeleteMessageBody {
     pub chat_id: BodyChatId,
     pub message_id: i32,
@@ -18,14 +22,28 @@ pub struct DeleteMessageBody {
 pub async fn delete_message(
     state: web::Data<Mutex<State>>,
     body: web::Json<DeleteMessageBody>,
+    me: web::Data<Me>,
 ) -> impl Responder {
     let mut lock = state.lock().unwrap();
-    check_if_message_exists!(lock, body.message_id);
-    let deleted_message = lock.messages.delete_message(body.message_id).unwrap();
-    lock.responses.deleted_messages.push(DeletedMessage {
+    let chat_id = ChatId(body.chat_id.id());
+    check_if_message_exists!(
+        lock,
+        chat_id,
+        body.message_id,
+        ApiError::MessageToDeleteNotFound
+    );
+    let message = lock.messages.get_message(chat_id, body.message_id).unwrap();
+    if !lock.can_be_deleted(&message, me.id) {
+        return BotApiError::new(ApiError::MessageCantBeDeleted).error_response();
+    }
+    let deleted_message = lock
+        .messages
+        .delete_message(chat_id, body.message_id)
+        .unwrap();
+    lock.record(BotCallKind::DeletedMessage(DeletedMessage {
         message: deleted_message.clone(),
         bot_request: body.into_inner(),
-    });
+    }));
 
     make_telegram_result(true)
 }
@@ -1,18 +1,19 @@
 use std::sync::Mutex;
 
 use actix_web::{web, Responder};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use teloxide::types::{ChatId, Me};
 
 use super::BodyChatId;
 use crate::{
     server::{
         routes::{delete_message::DeleteMessageBody, make_telegram_result},
-        DeletedMessage,
+        BotCallKind, DeletedMessage,
     },
     state::State,
 };
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DeleteMessagesBody {
     pub chat_id: BodyChatId,
     pub message_ids: Vec<i32>,
@@ -21,13 +22,25 @@ pub struct DeleteMessagesBody {
 pub async fn delete_messages(
     state: web::Data<Mutex<State>>,
     body: web::Json<DeleteMessagesBody>,
+    me: web::Data<Me>,
 ) -> impl Responder {
     let mut lock = state.lock().unwrap();
     let bot_request = body.into_inner();
-    // deleteMessages skips messages that are not found, no error is returned.
-    let mut deleted_messages = lock
+    let chat_id = ChatId(bot_request.chat_id.id());
+    // deleteMessages skips messages that are not found or can't be deleted, no error is returned.
+    let message_ids: Vec<i32> = bot_request
+        .message_ids
+        .iter()
+        .copied()
+        .filter(|id| {
+            lock.messages
+                .get_message(chat_id, *id)
+                .is_some_and(|message| lock.can_be_deleted(&message, me.id))
+        })
+        .collect();
+    let deleted_messages: Vec<DeletedMessage> = lock
         .messages
-        .delete_messages(&bot_request.message_ids)
+        .delete_messages(chat_id, &message_ids)
         .into_iter()
         .map(|m| DeletedMessage {
             message: m.clone(),
@@ -38,9 +51,9 @@ pub async fn delete_messages(
         })
         .collect();
 
-    lock.responses
-        .deleted_messages
-        .append(&mut deleted_messages);
+    for deleted_message in deleted_messages {
+        lock.record(BotCallKind::DeletedMessage(deleted_message));
+    }
 
     make_telegram_result(true)
 }
@@ -0,0 +1,26 @@
+use std::sync::Mutex;
+
+use actix_web::{web, Responder};
+use serde::{Deserialize, Serialize};
+
+use super::make_telegram_result;
+use crate::{server::BotCallKind, state::State};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeleteWebhookBody {
+    pub drop_pending_updates: Option<bool>,
+}
+
+pub async fn delete_webhook(
+    state: web::Data<Mutex<State>>,
+    body: web::Json<DeleteWebhookBody>,
+) -> impl Responder {
+    let mut lock = state.lock().unwrap();
+    lock.webhook = None;
+    if body.drop_pending_updates == Some(true) {
+        lock.update_queue.clear();
+    }
+    lock.record(BotCallKind::DeletedWebhook(body.into_inner()));
+
+    make_telegram_result(true)
+}
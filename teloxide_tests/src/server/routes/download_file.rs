@@ -1,12 +1,13 @@
 use std::{fmt::Error, sync::Mutex};
 
 use actix_web::{
-    error::ErrorBadRequest,
+    http::StatusCode,
     web::{self, Bytes},
     HttpResponse,
 };
 use futures_util::{future::ok, stream::once};
 
+use super::make_telegram_error;
 use crate::state::State;
 
 pub async fn download_file(
@@ -22,7 +23,8 @@ pub async fn download_file(
         .find(|f| f.path == path.1)
         .is_none()
     {
-        return ErrorBadRequest("No such file found").into();
+        // The file server answers like the bot api does to an unknown method
+        return make_telegram_error(StatusCode::NOT_FOUND, "Not Found".to_string());
     }
 
     let stream = once(ok::<_, Error>(Bytes::copy_from_slice(
@@ -1,16 +1,22 @@
 use std::sync::Mutex;
 
-use actix_web::{error::ErrorBadRequest, web, Responder};
-use serde::Deserialize;
-use teloxide::types::{BusinessConnectionId, MessageEntity, ParseMode, ReplyMarkup};
+use actix_web::{web, Responder, ResponseError};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::{BusinessConnectionId, ChatId, MessageEntity, ParseMode, ReplyMarkup},
+    ApiError,
+};
 
-use super::{check_if_message_exists, BodyChatId};
+use super::{check_if_message_exists, BodyChatId, BotApiError};
 use crate::{
-    server::{routes::make_telegram_result, EditedMessageCaption},
+    server::{
+        limits::check_caption, parse_mode::render_text, routes::make_telegram_result, BotCallKind,
+        EditedMessageCaption,
+    },
     state::State,
 };
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EditMessageCaptionBody {
     pub chat_id: Option<BodyChatId>,
     pub message_id: Option<i32>,
@@ -32,17 +38,34 @@ pub async fn edit_message_caption(
         body.message_id,
         body.inline_message_id.clone(),
     ) {
-        (Some(_), Some(message_id), None) => {
+        (Some(chat_id), Some(message_id), None) => {
+            let chat_id = ChatId(chat_id.id());
             let mut lock = state.lock().unwrap();
-            check_if_message_exists!(lock, message_id);
+            check_if_message_exists!(lock, chat_id, message_id, ApiError::MessageToEditNotFound);
+            let (caption, caption_entities) = match render_text(
+                &body.caption,
+                body.parse_mode,
+                body.caption_entities.clone(),
+            ) {
+                Ok(rendered) => rendered,
+                Err(err) => return err.error_response(),
+            };
+            if let Err(err) = check_caption(Some(&caption), ApiError::EditedMessageIsTooLong) {
+                return err.error_response();
+            }
+            let now = lock.clock.now();
+            lock.messages
+                .edit_message_field(chat_id, message_id, "edit_date", now.timestamp());
             lock.messages
-                .edit_message_field(message_id, "caption", body.caption.clone());
+                .edit_message_field(chat_id, message_id, "caption", caption);
             lock.messages.edit_message_field(
+                chat_id,
                 message_id,
                 "caption_entities",
-                body.caption_entities.clone().unwrap_or_default(),
+                caption_entities,
             );
             lock.messages.edit_message_field(
+                chat_id,
                 message_id,
                 "show_caption_above_media",
                 body.show_caption_above_media.unwrap_or(false),
@@ -50,19 +73,17 @@ pub async fn edit_message_caption(
 
             let message = lock
                 .messages
-                .edit_message_reply_markup(message_id, body.reply_markup.clone())
+                .edit_message_reply_markup(chat_id, message_id, body.reply_markup.clone())
                 .unwrap();
 
-            lock.responses
-                .edited_messages_caption
-                .push(EditedMessageCaption {
-                    message: message.clone(),
-                    bot_request: body.into_inner(),
-                });
+            lock.record(BotCallKind::EditedMessageCaption(EditedMessageCaption {
+                message: message.clone(),
+                bot_request: body.into_inner(),
+            }));
 
             make_telegram_result(message)
         }
         (None, None, Some(_)) => make_telegram_result(true),
-        _ => ErrorBadRequest("No message_id or inline_message_id were provided").into(),
+        _ => BotApiError::new(ApiError::MessageIdentifierNotSpecified).error_response(),
     }
 }
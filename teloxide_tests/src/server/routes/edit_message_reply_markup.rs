@@ -1,19 +1,22 @@
 use std::sync::Mutex;
 
-use actix_web::{error::ErrorBadRequest, web, Responder};
-use serde::Deserialize;
-use teloxide::types::{BusinessConnectionId, ReplyMarkup};
+use actix_web::{web, Responder, ResponseError};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::{BusinessConnectionId, ChatId, ReplyMarkup},
+    ApiError,
+};
 
-use super::BodyChatId;
+use super::{BodyChatId, BotApiError};
 use crate::{
     server::{
         routes::{check_if_message_exists, make_telegram_result},
-        EditedMessageReplyMarkup,
+        BotCallKind, EditedMessageReplyMarkup,
     },
     state::State,
 };
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EditMessageReplyMarkupBody {
     pub chat_id: Option<BodyChatId>,
     pub message_id: Option<i32>,
@@ -31,31 +34,26 @@ pub async fn edit_message_reply_markup(
         body.message_id,
         body.inline_message_id.clone(),
     ) {
-        (Some(_), Some(message_id), None) => {
+        (Some(chat_id), Some(message_id), None) => {
+            let chat_id = ChatId(chat_id.id());
             let mut lock = state.lock().unwrap();
-            check_if_message_exists!(lock, message_id);
+            check_if_message_exists!(lock, chat_id, message_id, ApiError::MessageToEditNotFound);
 
-            let message = match body.reply_markup.clone() {
-                Some(reply_markup) => lock
-                    .messages
-                    .edit_message_field(message_id, "reply_markup", reply_markup)
-                    .unwrap(),
-                None => lock
-                    .messages
-                    .edit_message_field(message_id, "reply_markup", None::<()>)
-                    .unwrap(),
-            };
+            let message = lock
+                .messages
+                .edit_message_reply_markup(chat_id, message_id, body.reply_markup.clone())
+                .unwrap();
 
-            lock.responses
-                .edited_messages_reply_markup
-                .push(EditedMessageReplyMarkup {
+            lock.record(BotCallKind::EditedMessageReplyMarkup(
+                EditedMessageReplyMarkup {
                     message: message.clone(),
                     bot_request: body.into_inner(),
-                });
+                },
+            ));
 
             make_telegram_result(message)
         }
         (None, None, Some(_)) => make_telegram_result(true),
-        _ => ErrorBadRequest("No message_id or inline_message_id were provided").into(),
+        _ => BotApiError::new(ApiError::MessageIdentifierNotSpecified).error_response(),
     }
 }
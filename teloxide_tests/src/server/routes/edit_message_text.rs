@@ -1,19 +1,24 @@
 use std::sync::Mutex;
 
-use actix_web::{error::ErrorBadRequest, web, Responder, ResponseError};
-use serde::Deserialize;
+use actix_web::{web, Responder, ResponseError};
+use serde::{Deserialize, Serialize};
 use teloxide::{
-    types::{BusinessConnectionId, LinkPreviewOptions, MessageEntity, ParseMode, ReplyMarkup},
+    types::{
+        BusinessConnectionId, ChatId, LinkPreviewOptions, MessageEntity, ParseMode, ReplyMarkup,
+    },
     ApiError,
 };
 
 use super::{BodyChatId, BotApiError};
 use crate::{
-    server::{routes::make_telegram_result, EditedMessageText},
+    server::{
+        limits::check_text, parse_mode::render_text, routes::make_telegram_result, BotCallKind,
+        EditedMessageText,
+    },
     state::State,
 };
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EditMessageTextBody {
     pub chat_id: Option<BodyChatId>,
     pub message_id: Option<i32>,
@@ -35,40 +40,52 @@ pub async fn edit_message_text(
         body.message_id,
         body.inline_message_id.clone(),
     ) {
-        (Some(_), Some(message_id), None) => {
+        (Some(chat_id), Some(message_id), None) => {
+            let chat_id = ChatId(chat_id.id());
             let mut lock = state.lock().unwrap();
-            let Some(old_message) = lock.messages.get_message(message_id) else {
+            let Some(old_message) = lock.messages.get_message(chat_id, message_id) else {
                 return BotApiError::new(ApiError::MessageToEditNotFound).error_response();
             };
 
+            let (text, entities) =
+                match render_text(&body.text, body.parse_mode, body.entities.clone()) {
+                    Ok(rendered) => rendered,
+                    Err(err) => return err.error_response(),
+                };
+            if let Err(err) = check_text(&text, ApiError::EditedMessageIsTooLong) {
+                return err.error_response();
+            }
             let old_reply_markup = old_message
                 .reply_markup()
                 .map(|kb| ReplyMarkup::InlineKeyboard(kb.clone()));
-            if old_message.text() == Some(&body.text) && old_reply_markup == body.reply_markup {
+            if old_message.text() == Some(&text)
+                && old_message.entities().unwrap_or_default() == entities
+                && old_reply_markup == body.reply_markup
+            {
                 return BotApiError::new(ApiError::MessageNotModified).error_response();
             }
 
+            let now = lock.clock.now();
+            lock.messages
+                .edit_message_field(chat_id, message_id, "edit_date", now.timestamp());
+            lock.messages
+                .edit_message_field(chat_id, message_id, "text", text);
             lock.messages
-                .edit_message_field(message_id, "text", body.text.clone());
-            lock.messages.edit_message_field(
-                message_id,
-                "entities",
-                body.entities.clone().unwrap_or(vec![]),
-            );
+                .edit_message_field(chat_id, message_id, "entities", entities);
             let message = lock
                 .messages
-                .edit_message_reply_markup(message_id, body.reply_markup.clone())
+                .edit_message_reply_markup(chat_id, message_id, body.reply_markup.clone())
                 .unwrap();
 
-            lock.responses.edited_messages_text.push(EditedMessageText {
+            lock.record(BotCallKind::EditedMessageText(EditedMessageText {
                 message: message.clone(),
                 bot_request: body.into_inner(),
-            });
+            }));
 
             make_telegram_result(message)
         }
         // No implementation for inline messages yet, so just return success
         (None, None, Some(_)) => make_telegram_result(true),
-        _ => ErrorBadRequest("No message_id or inline_message_id were provided").into(),
+        _ => BotApiError::new(ApiError::MessageIdentifierNotSpecified).error_response(),
     }
 }
@@ -1,16 +1,19 @@
 use std::sync::Mutex;
 
-use actix_web::{error::ErrorBadRequest, web, Responder};
-use serde::Deserialize;
-use teloxide::types::{Me, MessageId, MessageKind, MessageOrigin};
+use actix_web::{web, Responder, ResponseError};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::{ChatId, Me, MessageId, MessageKind, MessageOrigin},
+    ApiError,
+};
 
-use super::{make_telegram_result, BodyChatId};
+use super::{check_can_be_forwarded, make_telegram_result, BodyChatId, BotApiError};
 use crate::{
-    server::{routes::check_if_message_exists, ForwardedMessage},
+    server::{routes::check_if_message_exists, BotCallKind, ForwardedMessage},
     state::State,
 };
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ForwardMessageBody {
     pub chat_id: BodyChatId,
     pub from_chat_id: BodyChatId,
@@ -27,11 +30,20 @@ pub async fn forward_message(
 ) -> impl Responder {
     let mut lock = state.lock().unwrap();
 
-    check_if_message_exists!(lock, body.message_id);
-    let mut message = lock.messages.get_message(body.message_id).unwrap();
+    let from_chat_id = ChatId(body.from_chat_id.id());
+    check_if_message_exists!(
+        lock,
+        from_chat_id,
+        body.message_id,
+        ApiError::MessageToForwardNotFound
+    );
+    let mut message = lock
+        .messages
+        .get_message(from_chat_id, body.message_id)
+        .unwrap();
 
-    if message.has_protected_content() {
-        return ErrorBadRequest("Message has protected content").into();
+    if let Err(err) = check_can_be_forwarded(&message) {
+        return BotApiError::new(err).error_response();
     }
 
     let message_clone = message.clone();
@@ -64,17 +76,18 @@ pub async fn forward_message(
         common.has_protected_content = body.protect_content.unwrap_or(false);
     }
 
-    let last_id = lock.messages.max_message_id();
+    message.chat = lock.chats.chat(&body.chat_id);
+    let last_id = lock.messages.max_message_id(message.chat.id);
     message.id = MessageId(last_id + 1);
-    message.chat = body.chat_id.chat();
     message.from = Some(me.user.clone());
+    message.date = lock.clock.now();
     let message = lock.messages.add_message(message);
 
     lock.responses.sent_messages.push(message.clone());
-    lock.responses.forwarded_messages.push(ForwardedMessage {
+    lock.record(BotCallKind::ForwardedMessage(ForwardedMessage {
         message: message.clone(),
         bot_request: body.into_inner(),
-    });
+    }));
 
     make_telegram_result(message)
 }
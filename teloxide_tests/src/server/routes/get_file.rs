@@ -1,10 +1,10 @@
 use std::sync::Mutex;
 
-use actix_web::{error::ErrorBadRequest, web, Responder};
+use actix_web::{web, Responder, ResponseError};
 use serde::Deserialize;
-use teloxide::types::FileId;
+use teloxide::{types::FileId, ApiError};
 
-use super::make_telegram_result;
+use super::{make_telegram_result, BotApiError};
 use crate::state::State;
 
 #[derive(Deserialize)]
@@ -18,7 +18,7 @@ pub async fn get_file(
 ) -> impl Responder {
     let lock = state.lock().unwrap();
     let Some(file) = lock.files.iter().find(|f| f.id == query.file_id) else {
-        return ErrorBadRequest("File not found").into();
+        return BotApiError::new(ApiError::FileIdInvalid).error_response();
     };
     make_telegram_result(file)
 }
@@ -1,8 +1,54 @@
-use actix_web::Responder;
-use serde_json::json;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
-use super::make_telegram_result;
+use actix_web::{web, Responder, ResponseError};
+use serde::Deserialize;
+use teloxide::{types::AllowedUpdate, ApiError};
 
-pub async fn get_updates() -> impl Responder {
-    make_telegram_result(json!([]))
+use super::{make_telegram_result, BotApiError};
+use crate::state::State;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct GetUpdatesBody {
+    pub offset: Option<i32>,
+    pub limit: Option<u8>,
+    pub timeout: Option<u32>,
+    pub allowed_updates: Option<Vec<AllowedUpdate>>,
+}
+
+pub async fn get_updates(
+    state: web::Data<Mutex<State>>,
+    body: web::Json<GetUpdatesBody>,
+) -> impl Responder {
+    let limit = body.limit.unwrap_or(100).clamp(1, 100) as usize;
+    let deadline = Instant::now() + Duration::from_secs(body.timeout.unwrap_or(0) as u64);
+
+    {
+        let mut lock = state.lock().unwrap();
+        if lock.webhook.is_some() {
+            return BotApiError::new(ApiError::CantGetUpdates).error_response();
+        }
+        if let Some(offset) = body.offset {
+            let pending = lock.update_queue.len();
+            lock.update_queue.confirm(offset);
+            if lock.update_queue.len() != pending {
+                // Confirming updates means that the bot started working on them
+                lock.last_request_at = Some(Instant::now());
+            }
+        }
+        if let Some(allowed_updates) = body.allowed_updates.clone() {
+            lock.update_queue.set_allowed_updates(allowed_updates);
+        }
+    }
+
+    // Long polling: wait for the updates to arrive, or for the timeout to run out
+    loop {
+        let updates = state.lock().unwrap().update_queue.get_updates(limit);
+        if !updates.is_empty() || Instant::now() >= deadline {
+            return make_telegram_result(updates);
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
 }
@@ -1,10 +1,29 @@
-use actix_web::Responder;
+use std::sync::Mutex;
+
+use actix_web::{web, Responder};
 use serde_json::json;
 
 use super::make_telegram_result;
+use crate::state::State;
+
+pub async fn get_webhook_info(state: web::Data<Mutex<State>>) -> impl Responder {
+    let lock = state.lock().unwrap();
+    let pending_update_count = lock.update_queue.len();
+    let Some(webhook) = lock.webhook.clone() else {
+        // Telegram returns an empty url if no webhook is set
+        return make_telegram_result(json!({
+            "url": "",
+            "has_custom_certificate": false,
+            "pending_update_count": pending_update_count,
+        }));
+    };
 
-pub async fn get_webhook_info() -> impl Responder {
-    make_telegram_result(
-        json!({"url": "", "has_custom_certificate":false,"pending_update_count":0}),
-    )
+    make_telegram_result(json!({
+        "url": webhook.url,
+        "has_custom_certificate": webhook.certificate.is_some(),
+        "pending_update_count": pending_update_count,
+        "ip_address": webhook.ip_address,
+        "max_connections": webhook.max_connections.unwrap_or(40),
+        "allowed_updates": webhook.allowed_updates,
+    }))
 }
@@ -1,12 +1,18 @@
 use std::{collections::HashMap, str::from_utf8};
 
-use actix_web::{error::ResponseError, http::header::ContentType, HttpResponse};
+use actix_web::{
+    error::ResponseError,
+    http::{header::ContentType, StatusCode},
+    HttpResponse,
+};
 use futures_util::{stream::StreamExt as _, TryStreamExt};
-use rand::distr::{Alphanumeric, SampleString};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use teloxide::{
-    types::{Chat, MessageEntity, ParseMode, Seconds},
+    types::{
+        Chat, MediaKind, MediaPoll, Message, MessageCommon, MessageEntity, MessageKind, ParseMode,
+        PollType, Seconds,
+    },
     ApiError,
 };
 
@@ -17,6 +23,7 @@ pub mod ban_chat_member;
 pub mod copy_message;
 pub mod delete_message;
 pub mod delete_messages;
+pub mod delete_webhook;
 pub mod download_file;
 pub mod edit_message_caption;
 pub mod edit_message_reply_markup;
@@ -47,13 +54,14 @@ pub mod send_video_note;
 pub mod send_voice;
 pub mod set_message_reaction;
 pub mod set_my_commands;
+pub mod set_webhook;
 pub mod unban_chat_member;
 pub mod unpin_all_chat_messages;
 pub mod unpin_chat_message;
 
 /// Telegram accepts both `i64` and `String` for chat_id,
 /// so it is a wrapper for both
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(untagged)]
 pub enum BodyChatId {
     Text(String),
@@ -61,10 +69,13 @@ pub enum BodyChatId {
 }
 
 impl BodyChatId {
-    /// Returns the ID of the chat
+    /// Returns the ID of the chat. The usernames are turned into the ids before the request gets
+    /// to the route, so the text can only be a number here, and anything else is a bug
     pub fn id(&self) -> i64 {
         match self {
-            BodyChatId::Text(_) => 123456789,
+            BodyChatId::Text(text) => text
+                .parse()
+                .unwrap_or_else(|_| panic!("The chat id {text:?} wasn't resolved to a number")),
             BodyChatId::Id(id) => *id,
         }
     }
@@ -80,7 +91,7 @@ impl BodyChatId {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(untagged)]
 pub enum MediaGroupInputMedia {
     InputMediaAudio(MediaGroupInputMediaAudio),
@@ -89,7 +100,7 @@ pub enum MediaGroupInputMedia {
     InputMediaVideo(MediaGroupInputMediaVideo),
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MediaGroupInputMediaAudio {
     pub r#type: String,
     pub file_name: String,
@@ -102,7 +113,7 @@ pub struct MediaGroupInputMediaAudio {
     pub title: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MediaGroupInputMediaDocument {
     pub r#type: String,
     pub file_name: String,
@@ -113,7 +124,7 @@ pub struct MediaGroupInputMediaDocument {
     pub disable_content_type_detection: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MediaGroupInputMediaPhoto {
     pub r#type: String,
     pub file_name: String,
@@ -125,7 +136,7 @@ pub struct MediaGroupInputMediaPhoto {
     pub has_spoiler: Option<bool>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MediaGroupInputMediaVideo {
     pub r#type: String,
     pub file_name: String,
@@ -154,6 +165,7 @@ pub enum FileType {
     Animation,
 }
 
+#[derive(Clone, Debug, Serialize)]
 pub struct Attachment {
     pub raw_name: String,
     pub file_name: String,
@@ -173,11 +185,47 @@ pub trait SerializeRawFields {
 #[derive(Debug, Serialize)]
 struct TelegramResponse {
     ok: bool,
+    error_code: u16,
     description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parameters: Option<ResponseParameters>,
+}
+
+#[derive(Debug, Serialize)]
+struct ResponseParameters {
+    retry_after: u32,
+}
+
+/// Makes an error response the same way telegram does it
+pub fn make_telegram_error(status: StatusCode, description: String) -> HttpResponse {
+    make_telegram_error_response(TelegramResponse {
+        ok: false,
+        error_code: status.as_u16(),
+        description,
+        parameters: None,
+    })
+}
+
+/// Makes the response to a request that hit the flood control, which teloxide turns into
+/// `RequestError::RetryAfter`
+pub fn make_telegram_retry_after(retry_after: u32) -> HttpResponse {
+    make_telegram_error_response(TelegramResponse {
+        ok: false,
+        error_code: StatusCode::TOO_MANY_REQUESTS.as_u16(),
+        description: format!("Too Many Requests: retry after {retry_after}"),
+        parameters: Some(ResponseParameters { retry_after }),
+    })
+}
+
+fn make_telegram_error_response(response: TelegramResponse) -> HttpResponse {
+    let status = StatusCode::from_u16(response.error_code).unwrap();
+    HttpResponse::build(status)
+        .insert_header(ContentType::json())
+        .body(serde_json::to_string(&response).unwrap())
 }
 
 #[derive(Debug, PartialEq, Hash, Eq, Clone)]
-struct BotApiError {
+pub struct BotApiError {
     error: ApiError,
 }
 
@@ -185,6 +233,16 @@ impl BotApiError {
     pub fn new(error: ApiError) -> Self {
         Self { error }
     }
+
+    /// The description exactly as telegram sends it, so that teloxide parses it back into the
+    /// same error
+    fn description(&self) -> String {
+        match &self.error {
+            ApiError::InvalidToken => "Unauthorized".to_string(),
+            ApiError::Unknown(description) => description.clone(),
+            error => error.to_string(),
+        }
+    }
 }
 
 impl std::fmt::Display for BotApiError {
@@ -194,32 +252,96 @@ impl std::fmt::Display for BotApiError {
 }
 
 impl ResponseError for BotApiError {
-    fn status_code(&self) -> actix_web::http::StatusCode {
-        actix_web::http::StatusCode::BAD_REQUEST
+    fn status_code(&self) -> StatusCode {
+        if self.error == ApiError::RequestEntityTooLarge {
+            return StatusCode::PAYLOAD_TOO_LARGE;
+        }
+        // Telegram starts the descriptions with the name of the status
+        let description = self.description();
+        [
+            StatusCode::UNAUTHORIZED,
+            StatusCode::FORBIDDEN,
+            StatusCode::NOT_FOUND,
+            StatusCode::CONFLICT,
+        ]
+        .into_iter()
+        .find(|status| {
+            status
+                .canonical_reason()
+                .is_some_and(|reason| description.starts_with(reason))
+        })
+        .unwrap_or(StatusCode::BAD_REQUEST)
     }
 
     fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
-        let response = TelegramResponse {
-            ok: false,
-            description: self.error.to_string(),
-        };
-        HttpResponse::build(self.status_code())
-            .insert_header(ContentType::json())
-            .body(serde_json::to_string(&response).unwrap())
+        make_telegram_error(self.status_code(), self.description())
     }
 }
 
-// TODO: replace usages with appropriate error values from teloxide::ApiError.
+/// Returns the `ApiError` if the message doesn't exist in the chat
 macro_rules! check_if_message_exists {
-    ($lock:expr, $msg_id:expr) => {
-        if $lock.messages.get_message($msg_id).is_none() {
-            return ErrorBadRequest("Message not found").into();
+    ($lock:expr, $chat_id:expr, $msg_id:expr, $error:expr) => {
+        if $lock.messages.get_message($chat_id, $msg_id).is_none() {
+            return actix_web::ResponseError::error_response(
+                &$crate::server::routes::BotApiError::new($error),
+            );
         }
     };
 }
 
 pub(crate) use check_if_message_exists;
 
+/// Returns the error telegram gives if the message can't be forwarded: the protected ones (with
+/// their own description), and the service messages
+pub(crate) fn check_can_be_forwarded(message: &Message) -> Result<(), ApiError> {
+    let is_service = !matches!(
+        message.kind,
+        MessageKind::Common(_)
+            | MessageKind::Dice(_)
+            | MessageKind::Invoice(_)
+            | MessageKind::Giveaway(_)
+            | MessageKind::GiveawayWinners(_)
+    );
+    if message.has_protected_content() {
+        return Err(ApiError::Unknown(
+            "Bad Request: message has protected content and can't be forwarded".to_string(),
+        ));
+    }
+    if is_service {
+        return Err(ApiError::Unknown(
+            "Bad Request: message can't be forwarded".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the error telegram gives if the message can't be copied. Besides the protected ones
+/// (with their own description), only the regular messages can be copied, without the paid
+/// media, and the quizzes only if the bot knows their right answer
+pub(crate) fn check_can_be_copied(message: &Message) -> Result<(), ApiError> {
+    if message.has_protected_content() {
+        return Err(ApiError::Unknown(
+            "Bad Request: message has protected content and can't be copied".to_string(),
+        ));
+    }
+    let can_be_copied = match &message.kind {
+        MessageKind::Common(MessageCommon { media_kind, .. }) => match media_kind {
+            MediaKind::PaidMedia(_) => false,
+            MediaKind::Poll(MediaPoll { poll, .. }) => {
+                poll.poll_type != PollType::Quiz || poll.correct_option_id.is_some()
+            }
+            _ => true,
+        },
+        _ => false,
+    };
+    if !can_be_copied {
+        return Err(ApiError::Unknown(
+            "Bad Request: message can't be copied".to_string(),
+        ));
+    }
+    Ok(())
+}
+
 pub async fn get_raw_multipart_fields(
     payload: &mut actix_multipart::Multipart,
 ) -> (HashMap<String, String>, HashMap<String, Attachment>) {
@@ -241,13 +363,13 @@ pub async fn get_raw_multipart_fields(
             // Treat raw_fields with filenames as raw_attachments
             let mut attachment_key = fname.clone();
             if raw_attachments.contains_key(&fname) {
-                // If two files have the same name, add a random string to the filename
+                // If two files have the same name, add a number to the filename
                 attachment_key = fname
                     .split('.')
                     .enumerate()
                     .map(|(i, s)| {
                         if i == 0 {
-                            format!("{s}{}", Alphanumeric.sample_string(&mut rand::rng(), 5))
+                            format!("{s}{}", raw_attachments.len())
                         } else {
                             s.to_string()
                         }
@@ -288,6 +410,12 @@ pub async fn get_raw_multipart_fields(
     (fields, attachments)
 }
 
+/// Teloxide sends the `parse_mode` of the multipart requests as a plain string instead of JSON,
+/// so it has to be read from the raw field
+pub fn multipart_parse_mode(fields: &HashMap<String, String>) -> Option<ParseMode> {
+    fields.get("parse_mode")?.parse().ok()
+}
+
 pub fn make_telegram_result<T>(result: T) -> HttpResponse
 where
     T: Serialize,
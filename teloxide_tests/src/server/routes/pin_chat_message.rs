@@ -1,13 +1,19 @@
 use std::sync::Mutex;
 
-use actix_web::{error::ErrorBadRequest, web, Responder};
-use serde::Deserialize;
-use teloxide::types::BusinessConnectionId;
+use actix_web::{web, Responder};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::{BusinessConnectionId, ChatId},
+    ApiError,
+};
 
 use super::{check_if_message_exists, BodyChatId};
-use crate::{server::routes::make_telegram_result, state::State};
+use crate::{
+    server::{routes::make_telegram_result, BotCallKind},
+    state::State,
+};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PinChatMessageBody {
     pub chat_id: BodyChatId,
     pub message_id: i32,
@@ -20,7 +26,12 @@ pub async fn pin_chat_message(
     body: web::Json<PinChatMessageBody>,
 ) -> impl Responder {
     let mut lock = state.lock().unwrap();
-    check_if_message_exists!(lock, body.message_id);
-    lock.responses.pinned_chat_messages.push(body.into_inner());
+    check_if_message_exists!(
+        lock,
+        ChatId(body.chat_id.id()),
+        body.message_id,
+        ApiError::Unknown("Bad Request: message to pin not found".to_string())
+    );
+    lock.record(BotCallKind::PinnedChatMessage(body.into_inner()));
     make_telegram_result(true)
 }
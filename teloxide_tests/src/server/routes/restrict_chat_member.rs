@@ -1,13 +1,16 @@
 use std::sync::Mutex;
 
 use actix_web::{web, Responder};
-use serde::Deserialize;
-use teloxide::types::ChatPermissions;
+use serde::{Deserialize, Serialize};
+use teloxide::types::{ChatId, ChatPermissions, UserId};
 
 use super::BodyChatId;
-use crate::{server::routes::make_telegram_result, state::State};
+use crate::{
+    server::{routes::make_telegram_result, BotCallKind},
+    state::{Restriction, State},
+};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RestrictChatMemberBody {
     pub chat_id: BodyChatId,
     pub user_id: u64,
@@ -20,11 +23,16 @@ pub async fn restrict_chat_member(
     state: web::Data<Mutex<State>>,
     body: web::Json<RestrictChatMemberBody>,
 ) -> impl Responder {
-    // Idk what to verify here
     let mut lock = state.lock().unwrap();
-    lock.responses
-        .restricted_chat_members
-        .push(body.into_inner());
+    let until = lock.clock.until(body.until_date);
+    lock.restricted_users.insert(
+        (ChatId(body.chat_id.id()), UserId(body.user_id)),
+        Restriction {
+            permissions: body.permissions.clone(),
+            until,
+        },
+    );
+    lock.record(BotCallKind::RestrictedChatMember(body.into_inner()));
 
     make_telegram_result(true)
 }
@@ -1,21 +1,25 @@
 use std::{collections::HashMap, str::FromStr, sync::Mutex};
 
 use actix_multipart::Multipart;
-use actix_web::{error::ErrorBadRequest, web, Responder};
+use actix_web::{web, Responder, ResponseError};
 use mime::Mime;
-use rand::distr::{Alphanumeric, SampleString};
-use serde::Deserialize;
-use teloxide::types::{
-    BusinessConnectionId, EffectId, FileId, FileUniqueId, Me, MessageEntity, ParseMode,
-    ReplyMarkup, ReplyParameters, Seconds,
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::{
+        BusinessConnectionId, EffectId, Me, MessageEntity, ParseMode, ReplyMarkup, ReplyParameters,
+        Seconds,
+    },
+    ApiError,
 };
 
-use super::{get_raw_multipart_fields, make_telegram_result, BodyChatId};
+use super::{get_raw_multipart_fields, make_telegram_result, multipart_parse_mode, BodyChatId};
 use crate::{
     proc_macros::SerializeRawFields,
     server::{
+        limits::{caption_is_too_long, check_caption},
+        parse_mode::render_optional_text,
         routes::{check_if_message_exists, Attachment, FileType, SerializeRawFields},
-        SentMessageAudio,
+        BotCallKind, SentMessageAudio,
     },
     state::State,
     MockMessageAudio,
@@ -28,23 +32,41 @@ pub async fn send_audio(
 ) -> impl Responder {
     let (fields, attachments) = get_raw_multipart_fields(&mut payload).await;
     let mut lock = state.lock().unwrap();
-    let body =
+    let mut body =
         SendMessageAudioBody::serialize_raw_fields(&fields, &attachments, FileType::Audio).unwrap();
-    let chat = body.chat_id.chat();
+    body.parse_mode = multipart_parse_mode(&fields);
+    let chat = lock.chats.chat(&body.chat_id);
 
     let mut message = MockMessageAudio::new().chat(chat.clone());
     message.has_protected_content = body.protect_content.unwrap_or(false);
     message.from = Some(me.user.clone());
-    message.caption = body.caption.clone();
-    message.caption_entities = body.caption_entities.clone().unwrap_or_default();
+    message.date = lock.clock.now();
+    let (caption, caption_entities) = match render_optional_text(
+        body.caption.as_deref(),
+        body.parse_mode,
+        body.caption_entities.clone(),
+    ) {
+        Ok(rendered) => rendered,
+        Err(err) => return err.error_response(),
+    };
+    if let Err(err) = check_caption(caption.as_deref(), caption_is_too_long()) {
+        return err.error_response();
+    }
+    message.caption = caption;
+    message.caption_entities = caption_entities;
     message.effect_id = body.message_effect_id.clone();
     message.business_connection_id = body.business_connection_id.clone();
 
     if let Some(reply_parameters) = &body.reply_parameters {
-        check_if_message_exists!(lock, reply_parameters.message_id.0);
+        check_if_message_exists!(
+            lock,
+            message.chat.id,
+            reply_parameters.message_id.0,
+            ApiError::MessageToReplyNotFound
+        );
         let reply_to_message = lock
             .messages
-            .get_message(reply_parameters.message_id.0)
+            .get_message(message.chat.id, reply_parameters.message_id.0)
             .unwrap();
         message.reply_to_message = Some(Box::new(reply_to_message.clone()));
     }
@@ -52,8 +74,8 @@ pub async fn send_audio(
         message.reply_markup = Some(markup);
     }
 
-    let file_id = FileId(Alphanumeric.sample_string(&mut rand::rng(), 16));
-    let file_unique_id = FileUniqueId(Alphanumeric.sample_string(&mut rand::rng(), 8));
+    let file_id = lock.random.file_id();
+    let file_unique_id = lock.random.file_unique_id();
 
     message.file_id = file_id;
     message.file_unique_id = file_unique_id;
@@ -64,7 +86,7 @@ pub async fn send_audio(
     message.mime_type = Some(Mime::from_str("audio/mp3").unwrap());
     message.file_name = Some(body.file_name.clone());
 
-    let last_id = lock.messages.max_message_id();
+    let last_id = lock.messages.max_message_id(message.chat.id);
     let message = lock.messages.add_message(message.id(last_id + 1).build());
 
     lock.files.push(teloxide::types::File {
@@ -72,15 +94,16 @@ pub async fn send_audio(
         path: body.file_name.to_owned(),
     });
     lock.responses.sent_messages.push(message.clone());
-    lock.responses.sent_messages_audio.push(SentMessageAudio {
+    lock.show_reply_markup(&message, body.reply_markup.as_ref());
+    lock.record(BotCallKind::SentMessageAudio(SentMessageAudio {
         message: message.clone(),
         bot_request: body,
-    });
+    }));
 
     make_telegram_result(message)
 }
 
-#[derive(Debug, Clone, Deserialize, SerializeRawFields)]
+#[derive(Debug, Clone, Serialize, Deserialize, SerializeRawFields)]
 pub struct SendMessageAudioBody {
     pub chat_id: BodyChatId,
     pub message_thread_id: Option<i64>,
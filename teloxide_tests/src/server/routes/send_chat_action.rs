@@ -1,13 +1,16 @@
 use std::sync::Mutex;
 
 use actix_web::{web, Responder};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use teloxide::types::BusinessConnectionId;
 
 use super::BodyChatId;
-use crate::{server::routes::make_telegram_result, state::State};
+use crate::{
+    server::{routes::make_telegram_result, BotCallKind},
+    state::State,
+};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SendChatActionBody {
     pub chat_id: BodyChatId,
     pub message_thread_id: Option<i64>,
@@ -20,7 +23,7 @@ pub async fn send_chat_action(
     body: web::Json<SendChatActionBody>,
 ) -> impl Responder {
     let mut lock = state.lock().unwrap();
-    lock.responses.sent_chat_actions.push(body.into_inner());
+    lock.record(BotCallKind::SentChatAction(body.into_inner()));
 
     make_telegram_result(true)
 }
@@ -1,17 +1,20 @@
 use std::sync::Mutex;
 
-use actix_web::{error::ErrorBadRequest, web, Responder};
-use serde::Deserialize;
-use teloxide::types::{BusinessConnectionId, EffectId, Me, ReplyMarkup, ReplyParameters};
+use actix_web::{web, Responder};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::{BusinessConnectionId, EffectId, Me, ReplyMarkup, ReplyParameters},
+    ApiError,
+};
 
 use super::{make_telegram_result, BodyChatId};
 use crate::{
-    server::{routes::check_if_message_exists, SentMessageContact},
+    server::{routes::check_if_message_exists, BotCallKind, SentMessageContact},
     state::State,
     MockMessageContact,
 };
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SendMessageContactBody {
     pub chat_id: BodyChatId,
     pub message_thread_id: Option<i64>,
@@ -33,10 +36,11 @@ pub async fn send_contact(
     state: web::Data<Mutex<State>>,
 ) -> impl Responder {
     let mut lock = state.lock().unwrap();
-    let chat = body.chat_id.chat();
+    let chat = lock.chats.chat(&body.chat_id);
     let mut message = // Creates the message, which will be mutated to fit the needed shape
         MockMessageContact::new().chat(chat);
     message.from = Some(me.user.clone());
+    message.date = lock.clock.now();
     message.phone_number = body.phone_number.clone();
     message.first_name = body.first_name.clone();
     message.last_name = body.last_name.clone();
@@ -46,10 +50,15 @@ pub async fn send_contact(
     message.business_connection_id = body.business_connection_id.clone();
 
     if let Some(reply_parameters) = &body.reply_parameters {
-        check_if_message_exists!(lock, reply_parameters.message_id.0);
+        check_if_message_exists!(
+            lock,
+            message.chat.id,
+            reply_parameters.message_id.0,
+            ApiError::MessageToReplyNotFound
+        );
         let reply_to_message = lock
             .messages
-            .get_message(reply_parameters.message_id.0)
+            .get_message(message.chat.id, reply_parameters.message_id.0)
             .unwrap();
         message.reply_to_message = Some(Box::new(reply_to_message.clone()));
     }
@@ -57,16 +66,15 @@ pub async fn send_contact(
         message.reply_markup = Some(markup);
     }
 
-    let last_id = lock.messages.max_message_id();
+    let last_id = lock.messages.max_message_id(message.chat.id);
     let message = lock.messages.add_message(message.id(last_id + 1).build());
 
     lock.responses.sent_messages.push(message.clone());
-    lock.responses
-        .sent_messages_contact
-        .push(SentMessageContact {
-            message: message.clone(),
-            bot_request: body.into_inner(),
-        });
+    lock.show_reply_markup(&message, body.reply_markup.as_ref());
+    lock.record(BotCallKind::SentMessageContact(SentMessageContact {
+        message: message.clone(),
+        bot_request: body.into_inner(),
+    }));
 
     make_telegram_result(message)
 }
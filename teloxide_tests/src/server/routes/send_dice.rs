@@ -1,17 +1,20 @@
 use std::sync::Mutex;
 
-use actix_web::{error::ErrorBadRequest, web, Responder};
-use serde::Deserialize;
-use teloxide::types::{BusinessConnectionId, DiceEmoji, ReplyMarkup, ReplyParameters};
+use actix_web::{web, Responder};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::{BusinessConnectionId, DiceEmoji, ReplyMarkup, ReplyParameters},
+    ApiError,
+};
 
 use super::{make_telegram_result, BodyChatId};
 use crate::{
-    server::{routes::check_if_message_exists, SentMessageDice},
+    server::{routes::check_if_message_exists, BotCallKind, SentMessageDice},
     state::State,
     MockMessageDice,
 };
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SendMessageDiceBody {
     pub chat_id: BodyChatId,
     pub message_thread_id: Option<i64>,
@@ -29,24 +32,30 @@ pub async fn send_dice(
     body: web::Json<SendMessageDiceBody>,
 ) -> impl Responder {
     let mut lock = state.lock().unwrap();
-    let chat = body.chat_id.chat();
+    let chat = lock.chats.chat(&body.chat_id);
     let mut message = // Creates the message, which will be mutated to fit the needed shape
         MockMessageDice::new().chat(chat);
     message.emoji = body.emoji.clone().unwrap_or(MockMessageDice::EMOJI);
-    // Random from 1 to 5 because it fits all the emoji
-    message.value = (1 + rand::random::<u8>() % 5) as u8;
+    message.value = lock.random.dice_value();
+    message.date = lock.clock.now();
     if let Some(reply_parameters) = &body.reply_parameters {
-        check_if_message_exists!(lock, reply_parameters.message_id.0);
+        check_if_message_exists!(
+            lock,
+            message.chat.id,
+            reply_parameters.message_id.0,
+            ApiError::MessageToReplyNotFound
+        );
     }
 
-    let last_id = lock.messages.max_message_id();
+    let last_id = lock.messages.max_message_id(message.chat.id);
     let message = lock.messages.add_message(message.id(last_id + 1).build());
 
     lock.responses.sent_messages.push(message.clone());
-    lock.responses.sent_messages_dice.push(SentMessageDice {
+    lock.show_reply_markup(&message, body.reply_markup.as_ref());
+    lock.record(BotCallKind::SentMessageDice(SentMessageDice {
         message: message.clone(),
         bot_request: body.into_inner(),
-    });
+    }));
 
     make_telegram_result(message)
 }
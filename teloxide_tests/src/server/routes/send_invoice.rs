@@ -1,13 +1,17 @@
 use std::sync::Mutex;
 
 use actix_web::{web, Responder};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use teloxide::types::{LabeledPrice, Me, ReplyMarkup, ReplyParameters};
 
 use super::{make_telegram_result, BodyChatId};
-use crate::{server::SentMessageInvoice, state::State, MockMessageInvoice};
+use crate::{
+    server::{BotCallKind, SentMessageInvoice},
+    state::State,
+    MockMessageInvoice,
+};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SendMessageInvoiceBody {
     pub chat_id: BodyChatId,
     pub message_thread_id: Option<i64>,
@@ -46,7 +50,7 @@ pub async fn send_invoice(
 ) -> impl Responder {
     let mut lock = state.lock().unwrap();
 
-    let chat = body.chat_id.chat();
+    let chat = lock.chats.chat(&body.chat_id);
     let mut message = MockMessageInvoice::new()
         .chat(chat)
         .title(body.title.clone())
@@ -54,6 +58,7 @@ pub async fn send_invoice(
         .start_parameter(body.start_parameter.clone().unwrap_or("".to_owned()))
         .total_amount(body.prices.first().unwrap().amount);
     message.from = Some(me.user.clone());
+    message.date = lock.clock.now();
 
     // Commented until teloxides new release
     // message.has_protected_content = body.protect_content.unwrap_or(false);
@@ -70,16 +75,15 @@ pub async fn send_invoice(
     //     message.reply_markup = Some(markup);
     // }
 
-    let last_id = lock.messages.max_message_id();
+    let last_id = lock.messages.max_message_id(message.chat.id);
     let message = lock.messages.add_message(message.id(last_id + 1).build());
 
     lock.responses.sent_messages.push(message.clone());
-    lock.responses
-        .sent_messages_invoice
-        .push(SentMessageInvoice {
-            message: message.clone(),
-            bot_request: body.into_inner(),
-        });
+    lock.show_reply_markup(&message, body.reply_markup.as_ref());
+    lock.record(BotCallKind::SentMessageInvoice(SentMessageInvoice {
+        message: message.clone(),
+        bot_request: body.into_inner(),
+    }));
 
     make_telegram_result(message)
 }
@@ -1,22 +1,29 @@
 use std::{collections::HashMap, sync::Mutex};
 
 use actix_multipart::Multipart;
-use actix_web::{error::ErrorBadRequest, web, Responder};
-use rand::distr::{Alphanumeric, SampleString};
-use serde::Deserialize;
+use actix_web::{web, Responder, ResponseError};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use teloxide::types::{
-    BusinessConnectionId, EffectId, FileId, FileUniqueId, Me, MediaGroupId, Message, MessageEntity,
-    MessageId, ParseMode, ReplyParameters, Seconds,
+use teloxide::{
+    types::{
+        BusinessConnectionId, EffectId, Me, Message, MessageEntity, MessageId, ParseMode,
+        ReplyParameters, Seconds,
+    },
+    ApiError,
 };
 
 use super::{
-    get_raw_multipart_fields, make_telegram_result, Attachment, BodyChatId, MediaGroupInputMedia,
-    MediaGroupInputMediaAudio, MediaGroupInputMediaDocument, MediaGroupInputMediaPhoto,
-    MediaGroupInputMediaVideo,
+    get_raw_multipart_fields, make_telegram_result, Attachment, BodyChatId, BotApiError,
+    MediaGroupInputMedia, MediaGroupInputMediaAudio, MediaGroupInputMediaDocument,
+    MediaGroupInputMediaPhoto, MediaGroupInputMediaVideo,
 };
 use crate::{
-    server::{routes::check_if_message_exists, SentMediaGroup},
+    server::{
+        limits::{caption_is_too_long, check_caption},
+        parse_mode::render_optional_text,
+        routes::check_if_message_exists,
+        BotCallKind, SentMediaGroup,
+    },
     state::State,
     MockMessageAudio, MockMessageDocument, MockMessagePhoto, MockMessageVideo, MockPhotoSize,
     MockVideo,
@@ -31,43 +38,82 @@ pub async fn send_media_group(
     let mut lock = state.lock().unwrap();
     let body = SendMediaGroupBody::serialize_raw_fields(&fields, &attachments).unwrap();
     if body.media.len() > 10 {
-        return ErrorBadRequest("Too many media items").into();
+        return BotApiError::new(ApiError::TooMuchMessages).error_response();
     } else if body.media.len() < 2 {
-        return ErrorBadRequest("Too few media items").into();
+        return BotApiError::new(ApiError::Unknown(
+            "Bad Request: parameter \"media\" must be an array of 2-10 items".to_string(),
+        ))
+        .error_response();
     }
-    let chat = body.chat_id.chat();
+    // The captions are rendered first, so that a bad one doesn't leave half of the album sent
+    let mut captions = vec![];
+    for media in &body.media {
+        let (caption, parse_mode, caption_entities) = match media {
+            MediaGroupInputMedia::InputMediaAudio(audio) => {
+                (&audio.caption, audio.parse_mode, &audio.caption_entities)
+            }
+            MediaGroupInputMedia::InputMediaDocument(document) => (
+                &document.caption,
+                document.parse_mode,
+                &document.caption_entities,
+            ),
+            MediaGroupInputMedia::InputMediaPhoto(photo) => {
+                (&photo.caption, photo.parse_mode, &photo.caption_entities)
+            }
+            MediaGroupInputMedia::InputMediaVideo(video) => {
+                (&video.caption, video.parse_mode, &video.caption_entities)
+            }
+        };
+        let rendered =
+            render_optional_text(caption.as_deref(), parse_mode, caption_entities.clone())
+                .and_then(|(caption, entities)| {
+                    check_caption(caption.as_deref(), caption_is_too_long())?;
+                    Ok((caption, entities))
+                });
+        match rendered {
+            Ok(rendered) => captions.push(rendered),
+            Err(err) => return err.error_response(),
+        }
+    }
+    let chat = lock.chats.chat(&body.chat_id);
     let protect_content = body.protect_content;
     let message_effect_id = body.message_effect_id.clone();
     let business_connection_id = body.business_connection_id.clone();
     let mut reply_to_message = None;
     if let Some(reply_parameters) = &body.reply_parameters {
-        check_if_message_exists!(lock, reply_parameters.message_id.0);
+        check_if_message_exists!(
+            lock,
+            chat.id,
+            reply_parameters.message_id.0,
+            ApiError::MessageToReplyNotFound
+        );
         // All of messages in the media group are replying to the same message
         reply_to_message = Some(Box::new(
             lock.messages
-                .get_message(reply_parameters.message_id.0)
+                .get_message(chat.id, reply_parameters.message_id.0)
                 .unwrap(),
         ));
     }
-    let media_group_id = MediaGroupId(Alphanumeric.sample_string(&mut rand::rng(), 16));
+    let media_group_id = lock.random.media_group_id();
 
     let mut messages: Vec<Message> = vec![];
 
-    for media in &body.media {
-        let file_id = FileId(Alphanumeric.sample_string(&mut rand::rng(), 16));
-        let file_unique_id = FileUniqueId(Alphanumeric.sample_string(&mut rand::rng(), 8));
-        let last_id = lock.messages.max_message_id();
+    for (media, (caption, caption_entities)) in body.media.iter().zip(captions) {
+        let file_id = lock.random.file_id();
+        let file_unique_id = lock.random.file_unique_id();
+        let last_id = lock.messages.max_message_id(chat.id);
         let message: Message;
         match media {
             MediaGroupInputMedia::InputMediaAudio(audio) => {
                 let mut mock_message = MockMessageAudio::new();
                 mock_message.chat = chat.clone();
                 mock_message.from = Some(me.user.clone());
+                mock_message.date = lock.clock.now();
 
                 mock_message.has_protected_content = protect_content.unwrap_or(false);
                 mock_message.reply_to_message = reply_to_message.clone();
-                mock_message.caption = audio.caption.clone();
-                mock_message.caption_entities = audio.caption_entities.clone().unwrap_or_default();
+                mock_message.caption = caption;
+                mock_message.caption_entities = caption_entities;
                 mock_message.media_group_id = Some(media_group_id.clone());
                 mock_message.performer = audio.performer.clone();
                 mock_message.title = audio.title.clone();
@@ -93,12 +139,12 @@ pub async fn send_media_group(
                 let mut mock_message = MockMessageDocument::new();
                 mock_message.chat = chat.clone();
                 mock_message.from = Some(me.user.clone());
+                mock_message.date = lock.clock.now();
 
                 mock_message.has_protected_content = protect_content.unwrap_or(false);
                 mock_message.reply_to_message = reply_to_message.clone();
-                mock_message.caption = document.caption.clone();
-                mock_message.caption_entities =
-                    document.caption_entities.clone().unwrap_or_default();
+                mock_message.caption = caption;
+                mock_message.caption_entities = caption_entities;
                 mock_message.media_group_id = Some(media_group_id.clone());
                 mock_message.effect_id = message_effect_id.clone();
                 mock_message.business_connection_id = business_connection_id.clone();
@@ -121,11 +167,12 @@ pub async fn send_media_group(
                 let mut mock_message = MockMessagePhoto::new();
                 mock_message.chat = chat.clone();
                 mock_message.from = Some(me.user.clone());
+                mock_message.date = lock.clock.now();
 
                 mock_message.has_protected_content = protect_content.unwrap_or(false);
                 mock_message.reply_to_message = reply_to_message.clone();
-                mock_message.caption = photo.caption.clone();
-                mock_message.caption_entities = photo.caption_entities.clone().unwrap_or_default();
+                mock_message.caption = caption;
+                mock_message.caption_entities = caption_entities;
                 mock_message.media_group_id = Some(media_group_id.clone());
                 mock_message.effect_id = message_effect_id.clone();
                 mock_message.business_connection_id = business_connection_id.clone();
@@ -150,11 +197,12 @@ pub async fn send_media_group(
                 let mut mock_message = MockMessageVideo::new();
                 mock_message.chat = chat.clone();
                 mock_message.from = Some(me.user.clone());
+                mock_message.date = lock.clock.now();
 
                 mock_message.has_protected_content = protect_content.unwrap_or(false);
                 mock_message.reply_to_message = reply_to_message.clone();
-                mock_message.caption = video.caption.clone();
-                mock_message.caption_entities = video.caption_entities.clone().unwrap_or_default();
+                mock_message.caption = caption;
+                mock_message.caption_entities = caption_entities;
                 mock_message.media_group_id = Some(media_group_id.clone());
                 mock_message.effect_id = message_effect_id.clone();
                 mock_message.business_connection_id = business_connection_id.clone();
@@ -187,14 +235,14 @@ pub async fn send_media_group(
     }
 
     lock.responses.sent_messages.extend(messages.clone());
-    lock.responses.sent_media_group.push(SentMediaGroup {
+    lock.record(BotCallKind::SentMediaGroup(SentMediaGroup {
         messages: messages.clone(),
         bot_request: body,
-    });
+    }));
     make_telegram_result(messages)
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SendMediaGroupBody {
     pub chat_id: BodyChatId,
     pub message_thread_id: Option<i64>,
@@ -1,20 +1,26 @@
 use std::sync::Mutex;
 
-use actix_web::{error::ErrorBadRequest, web, Responder};
-use serde::Deserialize;
-use teloxide::types::{
-    BusinessConnectionId, EffectId, LinkPreviewOptions, Me, MessageEntity, ParseMode, ReplyMarkup,
-    ReplyParameters,
+use actix_web::{web, Responder, ResponseError};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::{
+        BusinessConnectionId, EffectId, LinkPreviewOptions, Me, MessageEntity, ParseMode,
+        ReplyMarkup, ReplyParameters,
+    },
+    ApiError,
 };
 
 use super::{make_telegram_result, BodyChatId};
 use crate::{
     dataset::message_common::MockMessageText,
-    server::{routes::check_if_message_exists, SentMessageText},
+    server::{
+        limits::check_text, parse_mode::render_text, routes::check_if_message_exists, BotCallKind,
+        SentMessageText,
+    },
     state::State,
 };
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SendMessageTextBody {
     pub chat_id: BodyChatId,
     pub text: String,
@@ -36,20 +42,33 @@ pub async fn send_message(
     state: web::Data<Mutex<State>>,
 ) -> impl Responder {
     let mut lock = state.lock().unwrap();
-    let chat = body.chat_id.chat();
+    let (text, entities) = match render_text(&body.text, body.parse_mode, body.entities.clone()) {
+        Ok(rendered) => rendered,
+        Err(err) => return err.error_response(),
+    };
+    if let Err(err) = check_text(&text, ApiError::MessageIsTooLong) {
+        return err.error_response();
+    }
+    let chat = lock.chats.chat(&body.chat_id);
     let mut message = // Creates the message, which will be mutated to fit the needed shape
-        MockMessageText::new().text(&body.text).chat(chat);
+        MockMessageText::new().text(&text).chat(chat);
     message.from = Some(me.user.clone());
+    message.date = lock.clock.now();
     message.has_protected_content = body.protect_content.unwrap_or(false);
     message.effect_id = body.message_effect_id.clone();
     message.business_connection_id = body.business_connection_id.clone();
 
-    message.entities = body.entities.clone().unwrap_or_default();
+    message.entities = entities;
     if let Some(reply_parameters) = &body.reply_parameters {
-        check_if_message_exists!(lock, reply_parameters.message_id.0);
+        check_if_message_exists!(
+            lock,
+            message.chat.id,
+            reply_parameters.message_id.0,
+            ApiError::MessageToReplyNotFound
+        );
         let reply_to_message = lock
             .messages
-            .get_message(reply_parameters.message_id.0)
+            .get_message(message.chat.id, reply_parameters.message_id.0)
             .unwrap();
         message.reply_to_message = Some(Box::new(reply_to_message.clone()));
     }
@@ -57,14 +76,15 @@ pub async fn send_message(
         message.reply_markup = Some(markup);
     }
 
-    let last_id = lock.messages.max_message_id();
+    let last_id = lock.messages.max_message_id(message.chat.id);
     let message = lock.messages.add_message(message.id(last_id + 1).build());
 
     lock.responses.sent_messages.push(message.clone());
-    lock.responses.sent_messages_text.push(SentMessageText {
+    lock.show_reply_markup(&message, body.reply_markup.as_ref());
+    lock.record(BotCallKind::SentMessageText(SentMessageText {
         message: message.clone(),
         bot_request: body.into_inner(),
-    });
+    }));
 
     make_telegram_result(message)
 }
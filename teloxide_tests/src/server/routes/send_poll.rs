@@ -1,21 +1,29 @@
 use std::sync::Mutex;
 
-use actix_web::{error::ErrorBadRequest, web, Responder};
+use actix_web::{web, Responder, ResponseError};
 use chrono::DateTime;
-use serde::Deserialize;
-use teloxide::types::{
-    BusinessConnectionId, EffectId, InputPollOption, Me, MessageEntity, ParseMode, PollOption,
-    PollType, ReplyMarkup, ReplyParameters, Seconds,
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::{
+        BusinessConnectionId, EffectId, InputPollOption, Me, MessageEntity, ParseMode, PollOption,
+        PollType, ReplyMarkup, ReplyParameters, Seconds,
+    },
+    ApiError,
 };
 
 use super::{make_telegram_result, BodyChatId};
 use crate::{
-    server::{routes::check_if_message_exists, SentMessagePoll},
+    server::{
+        limits::check_poll,
+        parse_mode::{render_optional_text, render_text},
+        routes::check_if_message_exists,
+        BotCallKind, SentMessagePoll,
+    },
     state::State,
     MockMessagePoll,
 };
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SendMessagePollBody {
     pub chat_id: BodyChatId,
     pub message_thread_id: Option<i64>,
@@ -31,7 +39,7 @@ pub struct SendMessagePollBody {
     pub explanation_parse_mode: Option<ParseMode>,
     pub explanation_entities: Option<Vec<MessageEntity>>,
     pub open_period: Option<Seconds>,
-    pub close_date: Option<u16>,
+    pub close_date: Option<i64>,
     pub is_closed: Option<bool>,
     pub disable_notification: Option<bool>,
     pub protect_content: Option<bool>,
@@ -46,15 +54,36 @@ pub async fn send_poll(
     body: web::Json<SendMessagePollBody>,
     me: web::Data<Me>,
 ) -> impl Responder {
+    let (question, question_entities) = match render_text(
+        &body.question,
+        body.question_parse_mode,
+        body.question_entities.clone(),
+    ) {
+        Ok(rendered) => rendered,
+        Err(err) => return err.error_response(),
+    };
+    if let Err(err) = check_poll(&question, &body.options) {
+        return err.error_response();
+    }
+    let (explanation, explanation_entities) = match render_optional_text(
+        body.explanation.as_deref(),
+        body.explanation_parse_mode,
+        body.explanation_entities.clone(),
+    ) {
+        Ok(rendered) => rendered,
+        Err(err) => return err.error_response(),
+    };
+
     let mut lock = state.lock().unwrap();
-    let chat = body.chat_id.chat();
+    let chat = lock.chats.chat(&body.chat_id);
     let mut message = // Creates the message, which will be mutated to fit the needed shape
         MockMessagePoll::new().chat(chat);
     message.from = Some(me.user.clone());
+    message.date = lock.clock.now();
     message.has_protected_content = body.protect_content.unwrap_or(false);
     message.business_connection_id = body.business_connection_id.clone();
 
-    message.question = body.question.clone();
+    message.question = question;
     let mut options = vec![];
     for option in body.options.iter() {
         options.push(PollOption {
@@ -68,18 +97,28 @@ pub async fn send_poll(
     message.poll_type = body.r#type.clone().unwrap_or(PollType::Regular);
     message.allows_multiple_answers = body.allows_multiple_answers.unwrap_or(false);
     message.correct_option_id = body.correct_option_id;
-    message.explanation = body.explanation.clone();
-    message.explanation_entities = body.explanation_entities.clone();
+    message.explanation = explanation;
+    message.explanation_entities = Some(explanation_entities).filter(|e| !e.is_empty());
     message.open_period = body.open_period;
-    message.close_date = DateTime::from_timestamp(body.close_date.unwrap_or(0) as i64, 0);
+    message.close_date = match (body.open_period, body.close_date) {
+        (Some(open_period), _) => Some(lock.clock.now() + open_period.duration()),
+        (None, Some(close_date)) => DateTime::from_timestamp(close_date, 0),
+        (None, None) => None,
+    };
+    message.is_closed = body.is_closed.unwrap_or(false);
     message.effect_id = body.message_effect_id.clone();
-    message.question_entities = body.question_entities.clone();
+    message.question_entities = Some(question_entities).filter(|e| !e.is_empty());
 
     if let Some(reply_parameters) = &body.reply_parameters {
-        check_if_message_exists!(lock, reply_parameters.message_id.0);
+        check_if_message_exists!(
+            lock,
+            message.chat.id,
+            reply_parameters.message_id.0,
+            ApiError::MessageToReplyNotFound
+        );
         let reply_to_message = lock
             .messages
-            .get_message(reply_parameters.message_id.0)
+            .get_message(message.chat.id, reply_parameters.message_id.0)
             .unwrap();
         message.reply_to_message = Some(Box::new(reply_to_message.clone()));
     }
@@ -87,14 +126,15 @@ pub async fn send_poll(
         message.reply_markup = Some(markup);
     }
 
-    let last_id = lock.messages.max_message_id();
+    let last_id = lock.messages.max_message_id(message.chat.id);
     let message = lock.messages.add_message(message.id(last_id + 1).build());
 
     lock.responses.sent_messages.push(message.clone());
-    lock.responses.sent_messages_poll.push(SentMessagePoll {
+    lock.show_reply_markup(&message, body.reply_markup.as_ref());
+    lock.record(BotCallKind::SentMessagePoll(SentMessagePoll {
         message: message.clone(),
         bot_request: body.into_inner(),
-    });
+    }));
 
     make_telegram_result(message)
 }
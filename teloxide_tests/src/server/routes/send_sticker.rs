@@ -1,16 +1,19 @@
 use std::{collections::HashMap, sync::Mutex};
 
 use actix_multipart::Multipart;
-use actix_web::{error::ErrorBadRequest, web, Responder};
-use serde::Deserialize;
-use teloxide::types::{BusinessConnectionId, EffectId, Me, ReplyMarkup, ReplyParameters};
+use actix_web::{web, Responder};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::{BusinessConnectionId, EffectId, Me, ReplyMarkup, ReplyParameters},
+    ApiError,
+};
 
 use super::{get_raw_multipart_fields, make_telegram_result, BodyChatId};
 use crate::{
     proc_macros::SerializeRawFields,
     server::{
         routes::{check_if_message_exists, Attachment, FileType, SerializeRawFields},
-        SentMessageSticker,
+        BotCallKind, SentMessageSticker,
     },
     state::State,
     MockMessageSticker,
@@ -26,10 +29,11 @@ pub async fn send_sticker(
     let body =
         SendMessageStickerBody::serialize_raw_fields(&fields, &attachments, FileType::Sticker)
             .unwrap();
-    let chat = body.chat_id.chat();
+    let chat = lock.chats.chat(&body.chat_id);
 
     let mut message = MockMessageSticker::new().chat(chat);
     message.from = Some(me.user.clone());
+    message.date = lock.clock.now();
     message.has_protected_content = body.protect_content.unwrap_or(false);
     message.emoji = body.emoji.clone();
     message.effect_id = body.message_effect_id.clone();
@@ -39,10 +43,15 @@ pub async fn send_sticker(
     // ain't nobody testing that
 
     if let Some(reply_parameters) = &body.reply_parameters {
-        check_if_message_exists!(lock, reply_parameters.message_id.0);
+        check_if_message_exists!(
+            lock,
+            message.chat.id,
+            reply_parameters.message_id.0,
+            ApiError::MessageToReplyNotFound
+        );
         let reply_to_message = lock
             .messages
-            .get_message(reply_parameters.message_id.0)
+            .get_message(message.chat.id, reply_parameters.message_id.0)
             .unwrap();
         message.reply_to_message = Some(Box::new(reply_to_message.clone()));
     }
@@ -50,7 +59,7 @@ pub async fn send_sticker(
         message.reply_markup = Some(markup);
     }
 
-    let last_id = lock.messages.max_message_id();
+    let last_id = lock.messages.max_message_id(message.chat.id);
     let message = lock.messages.add_message(message.id(last_id + 1).build());
 
     lock.files.push(teloxide::types::File {
@@ -58,17 +67,16 @@ pub async fn send_sticker(
         path: body.file_name.to_owned(),
     });
     lock.responses.sent_messages.push(message.clone());
-    lock.responses
-        .sent_messages_sticker
-        .push(SentMessageSticker {
-            message: message.clone(),
-            bot_request: body,
-        });
+    lock.show_reply_markup(&message, body.reply_markup.as_ref());
+    lock.record(BotCallKind::SentMessageSticker(SentMessageSticker {
+        message: message.clone(),
+        bot_request: body,
+    }));
 
     make_telegram_result(message)
 }
 
-#[derive(Debug, Clone, Deserialize, SerializeRawFields)]
+#[derive(Debug, Clone, Serialize, Deserialize, SerializeRawFields)]
 pub struct SendMessageStickerBody {
     pub chat_id: BodyChatId,
     pub file_name: String,
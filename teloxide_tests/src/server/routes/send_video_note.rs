@@ -1,11 +1,11 @@
 use std::{collections::HashMap, sync::Mutex};
 
 use actix_multipart::Multipart;
-use actix_web::{error::ErrorBadRequest, web, Responder};
-use rand::distr::{Alphanumeric, SampleString};
-use serde::Deserialize;
-use teloxide::types::{
-    BusinessConnectionId, EffectId, FileId, FileUniqueId, Me, ReplyMarkup, ReplyParameters, Seconds,
+use actix_web::{web, Responder};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::{BusinessConnectionId, EffectId, Me, ReplyMarkup, ReplyParameters, Seconds},
+    ApiError,
 };
 
 use super::{get_raw_multipart_fields, make_telegram_result, BodyChatId};
@@ -13,7 +13,7 @@ use crate::{
     proc_macros::SerializeRawFields,
     server::{
         routes::{check_if_message_exists, Attachment, FileType, SerializeRawFields},
-        SentMessageVideoNote,
+        BotCallKind, SentMessageVideoNote,
     },
     state::State,
     MockMessageVideoNote,
@@ -29,17 +29,23 @@ pub async fn send_video_note(
     let body =
         SendMessageVideoNoteBody::serialize_raw_fields(&fields, &attachments, FileType::Voice)
             .unwrap();
-    let chat = body.chat_id.chat();
+    let chat = lock.chats.chat(&body.chat_id);
 
     let mut message = MockMessageVideoNote::new().chat(chat.clone());
     message.from = Some(me.user.clone());
+    message.date = lock.clock.now();
     message.has_protected_content = body.protect_content.unwrap_or(false);
 
     if let Some(reply_parameters) = &body.reply_parameters {
-        check_if_message_exists!(lock, reply_parameters.message_id.0);
+        check_if_message_exists!(
+            lock,
+            message.chat.id,
+            reply_parameters.message_id.0,
+            ApiError::MessageToReplyNotFound
+        );
         let reply_to_message = lock
             .messages
-            .get_message(reply_parameters.message_id.0)
+            .get_message(message.chat.id, reply_parameters.message_id.0)
             .unwrap();
         message.reply_to_message = Some(Box::new(reply_to_message.clone()));
     }
@@ -47,8 +53,8 @@ pub async fn send_video_note(
         message.reply_markup = Some(markup);
     }
 
-    let file_id = FileId(Alphanumeric.sample_string(&mut rand::rng(), 16));
-    let file_unique_id = FileUniqueId(Alphanumeric.sample_string(&mut rand::rng(), 8));
+    let file_id = lock.random.file_id();
+    let file_unique_id = lock.random.file_unique_id();
 
     message.file_id = file_id;
     message.file_unique_id = file_unique_id;
@@ -58,7 +64,7 @@ pub async fn send_video_note(
     message.effect_id = body.message_effect_id.clone();
     message.business_connection_id = body.business_connection_id.clone();
 
-    let last_id = lock.messages.max_message_id();
+    let last_id = lock.messages.max_message_id(message.chat.id);
     let message = lock.messages.add_message(message.id(last_id + 1).build());
 
     lock.files.push(teloxide::types::File {
@@ -66,17 +72,16 @@ pub async fn send_video_note(
         path: body.file_name.to_owned(),
     });
     lock.responses.sent_messages.push(message.clone());
-    lock.responses
-        .sent_messages_video_note
-        .push(SentMessageVideoNote {
-            message: message.clone(),
-            bot_request: body,
-        });
+    lock.show_reply_markup(&message, body.reply_markup.as_ref());
+    lock.record(BotCallKind::SentMessageVideoNote(SentMessageVideoNote {
+        message: message.clone(),
+        bot_request: body,
+    }));
 
     make_telegram_result(message)
 }
 
-#[derive(Debug, Clone, Deserialize, SerializeRawFields)]
+#[derive(Debug, Clone, Serialize, Deserialize, SerializeRawFields)]
 pub struct SendMessageVideoNoteBody {
     pub chat_id: BodyChatId,
     pub message_thread_id: Option<i64>,
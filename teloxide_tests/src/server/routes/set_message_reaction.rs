@@ -1,13 +1,19 @@
 use std::sync::Mutex;
 
-use actix_web::{error::ErrorBadRequest, web, Responder};
-use serde::Deserialize;
-use teloxide::types::ReactionType;
+use actix_web::{web, Responder};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::{ChatId, ReactionType},
+    ApiError,
+};
 
 use super::{make_telegram_result, BodyChatId};
-use crate::{server::routes::check_if_message_exists, state::State};
+use crate::{
+    server::{routes::check_if_message_exists, BotCallKind},
+    state::State,
+};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SetMessageReactionBody {
     pub chat_id: BodyChatId,
     pub message_id: i32,
@@ -21,9 +27,14 @@ pub async fn set_message_reaction(
 ) -> impl Responder {
     let mut lock = state.lock().unwrap();
 
-    check_if_message_exists!(lock, body.message_id);
+    check_if_message_exists!(
+        lock,
+        ChatId(body.chat_id.id()),
+        body.message_id,
+        ApiError::Unknown("Bad Request: message to react not found".to_string())
+    );
 
-    lock.responses.set_message_reaction.push(body.into_inner());
+    lock.record(BotCallKind::SetMessageReaction(body.into_inner()));
 
     make_telegram_result(true)
 }
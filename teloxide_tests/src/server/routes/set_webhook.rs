@@ -0,0 +1,96 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use actix_multipart::Multipart;
+use actix_web::{web, Either, Responder, ResponseError};
+use serde::{Deserialize, Serialize};
+use teloxide::{types::AllowedUpdate, ApiError};
+
+use super::{get_raw_multipart_fields, make_telegram_result, Attachment, BotApiError};
+use crate::{server::BotCallKind, state::State};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetWebhookBody {
+    pub url: String,
+    /// The file name of the uploaded certificate, if there was one. It can only be uploaded with
+    /// multipart
+    #[serde(skip_deserializing)]
+    pub certificate: Option<String>,
+    pub ip_address: Option<String>,
+    pub max_connections: Option<u8>,
+    pub allowed_updates: Option<Vec<AllowedUpdate>>,
+    pub drop_pending_updates: Option<bool>,
+    pub secret_token: Option<String>,
+}
+
+impl SetWebhookBody {
+    fn serialize_raw_fields(
+        fields: &HashMap<String, String>,
+        attachments: &HashMap<String, Attachment>,
+    ) -> Option<Self> {
+        // The certificate is either uploaded as the `certificate` field itself, or attached by
+        // the name in it, the way teloxide does it
+        let certificate = fields
+            .get("certificate")
+            .and_then(|value| value.strip_prefix("attach://"))
+            .unwrap_or("certificate");
+        Some(Self {
+            url: fields.get("url")?.to_string(),
+            certificate: attachments
+                .values()
+                .find(|attachment| attachment.raw_name == certificate)
+                .map(|attachment| attachment.file_name.clone()),
+            ip_address: fields.get("ip_address").cloned(),
+            max_connections: fields
+                .get("max_connections")
+                .map(|s| s.parse())
+                .transpose()
+                .ok()?,
+            allowed_updates: fields
+                .get("allowed_updates")
+                .map(|s| serde_json::from_str(s))
+                .transpose()
+                .ok()?,
+            drop_pending_updates: fields
+                .get("drop_pending_updates")
+                .map(|s| s.parse())
+                .transpose()
+                .ok()?,
+            secret_token: fields.get("secret_token").cloned(),
+        })
+    }
+}
+
+/// Teloxide always sends the webhook as multipart, but the other libraries send it as JSON if
+/// there is no certificate
+pub async fn set_webhook(
+    body: Either<web::Json<SetWebhookBody>, Multipart>,
+    state: web::Data<Mutex<State>>,
+) -> impl Responder {
+    let body = match body {
+        Either::Left(body) => body.into_inner(),
+        Either::Right(mut payload) => {
+            let (fields, attachments) = get_raw_multipart_fields(&mut payload).await;
+            let Some(body) = SetWebhookBody::serialize_raw_fields(&fields, &attachments) else {
+                return BotApiError::new(ApiError::Unknown(
+                    "Bad Request: bad webhook parameters".to_string(),
+                ))
+                .error_response();
+            };
+            body
+        }
+    };
+
+    let mut lock = state.lock().unwrap();
+    if body.drop_pending_updates == Some(true) {
+        lock.update_queue.clear();
+    }
+    if body.url.is_empty() {
+        // Telegram treats an empty url as a request to remove the webhook
+        lock.webhook = None;
+    } else {
+        lock.webhook = Some(body.clone());
+    }
+    lock.record(BotCallKind::SetWebhook(body));
+
+    make_telegram_result(true)
+}
@@ -1,12 +1,16 @@
 use std::sync::Mutex;
 
 use actix_web::{web, Responder};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use teloxide::types::{ChatId, UserId};
 
 use super::BodyChatId;
-use crate::{server::routes::make_telegram_result, state::State};
+use crate::{
+    server::{routes::make_telegram_result, BotCallKind},
+    state::State,
+};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UnbanChatMemberBody {
     pub chat_id: BodyChatId,
     pub user_id: u64,
@@ -17,9 +21,10 @@ pub async fn unban_chat_member(
     state: web::Data<Mutex<State>>,
     body: web::Json<UnbanChatMemberBody>,
 ) -> impl Responder {
-    // Idk what to verify here
     let mut lock = state.lock().unwrap();
-    lock.responses.unbanned_chat_members.push(body.into_inner());
+    lock.banned_users
+        .remove(&(ChatId(body.chat_id.id()), UserId(body.user_id)));
+    lock.record(BotCallKind::UnbannedChatMember(body.into_inner()));
 
     make_telegram_result(true)
 }
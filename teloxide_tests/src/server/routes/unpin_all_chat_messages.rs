@@ -1,12 +1,15 @@
 use std::sync::Mutex;
 
 use actix_web::{web, Responder};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use super::BodyChatId;
-use crate::{server::routes::make_telegram_result, state::State};
+use crate::{
+    server::{routes::make_telegram_result, BotCallKind},
+    state::State,
+};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UnpinAllChatMessagesBody {
     pub chat_id: BodyChatId,
 }
@@ -16,9 +19,7 @@ pub async fn unpin_all_chat_messages(
     body: web::Json<UnpinAllChatMessagesBody>,
 ) -> impl Responder {
     let mut lock = state.lock().unwrap();
-    lock.responses
-        .unpinned_all_chat_messages
-        .push(body.into_inner());
+    lock.record(BotCallKind::UnpinnedAllChatMessages(body.into_inner()));
 
     make_telegram_result(true)
 }
@@ -1,13 +1,19 @@
 use std::sync::Mutex;
 
-use actix_web::{error::ErrorBadRequest, web, Responder};
-use serde::Deserialize;
-use teloxide::types::BusinessConnectionId;
+use actix_web::{web, Responder};
+use serde::{Deserialize, Serialize};
+use teloxide::{
+    types::{BusinessConnectionId, ChatId},
+    ApiError,
+};
 
 use super::{check_if_message_exists, BodyChatId};
-use crate::{server::routes::make_telegram_result, state::State};
+use crate::{
+    server::{routes::make_telegram_result, BotCallKind},
+    state::State,
+};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UnpinChatMessageBody {
     pub chat_id: BodyChatId,
     pub message_id: Option<i32>,
@@ -20,11 +26,14 @@ pub async fn unpin_chat_message(
 ) -> impl Responder {
     let mut lock = state.lock().unwrap();
     if let Some(message_id) = body.message_id {
-        check_if_message_exists!(lock, message_id);
+        check_if_message_exists!(
+            lock,
+            ChatId(body.chat_id.id()),
+            message_id,
+            ApiError::Unknown("Bad Request: message to unpin not found".to_string())
+        );
     }
-    lock.responses
-        .unpinned_chat_messages
-        .push(body.into_inner());
+    lock.record(BotCallKind::UnpinnedChatMessage(body.into_inner()));
 
     make_telegram_result(true)
 }
@@ -0,0 +1,92 @@
+use std::{
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::Mutex,
+};
+
+use actix_web::{
+    body::BoxBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::ResponseError,
+    http::StatusCode,
+    middleware::Next,
+    web::Data,
+};
+use serde_json::Value;
+use teloxide::ApiError;
+
+use super::{
+    messages::Messages,
+    method_name, request_body,
+    routes::{make_telegram_error, make_telegram_result, BotApiError},
+    Responses,
+};
+use crate::state::State;
+
+/// What a stub can see and change in the fake server
+pub struct StubState<'a> {
+    pub messages: &'a mut Messages,
+    pub responses: &'a mut Responses,
+}
+
+/// A handler of an endpoint, set with [`MockBot::stub_endpoint`]
+///
+/// [`MockBot::stub_endpoint`]: crate::MockBot::stub_endpoint
+pub(crate) type Stub =
+    Box<dyn Fn(Value, &mut StubState) -> Result<Value, ApiError> + Send + Sync + 'static>;
+
+/// Calls the stub of the method instead of the route, if there is one
+pub(crate) async fn call_stub(
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let Some(state) = req.app_data::<Data<Mutex<State>>>().cloned() else {
+        return next.call(req).await;
+    };
+    let (Some(method), Some(body)) = (method_name(&req), request_body(&req)) else {
+        return next.call(req).await;
+    };
+    if !state.lock().unwrap().stubs.contains_key(&method) {
+        return next.call(req).await;
+    }
+
+    let body = body.json.clone();
+    let mut lock = state.lock().unwrap();
+    let State {
+        stubs,
+        messages,
+        responses,
+        ..
+    } = &mut *lock;
+    // A stub that panics (like a failed assert) would poison the state for everyone else, so the
+    // panic is caught here and turned into a server error. The panic itself is kept, and the
+    // `MockBot` raises it again in the test once the dispatch is over
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        stubs[&method](
+            body,
+            &mut StubState {
+                messages,
+                responses,
+            },
+        )
+    }));
+    drop(lock);
+
+    let response = match result {
+        Ok(Ok(result)) => make_telegram_result(result),
+        Ok(Err(error)) => BotApiError::new(error).error_response(),
+        Err(panic) => {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            // Only the first panic is raised, the rest are most likely caused by it
+            state.lock().unwrap().stub_panic.get_or_insert(panic);
+            make_telegram_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("The stub of {method} panicked: {message}"),
+            )
+        }
+    };
+    Ok(req.into_response(response))
+}
@@ -0,0 +1,131 @@
+use teloxide::types::{AllowedUpdate, Update};
+
+/// Updates that are waiting to be fetched by `getUpdates`, just like the ones Telegram keeps
+/// for every bot until they are confirmed
+#[derive(Default)]
+pub struct UpdateQueue {
+    pub updates: Vec<Update>,
+    allowed_updates: Option<Vec<AllowedUpdate>>,
+}
+
+impl UpdateQueue {
+    pub fn push(&mut self, update: Update) {
+        self.updates.push(update);
+    }
+
+    pub fn len(&self) -> usize {
+        self.updates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// Drops all of the pending updates, like `drop_pending_updates` does
+    pub fn clear(&mut self) {
+        self.updates.clear();
+    }
+
+    /// Confirms (and forgets) the updates before the offset. Negative offsets keep only the
+    /// last `-offset` updates, the same way telegram does it.
+    pub fn confirm(&mut self, offset: i32) {
+        if offset < 0 {
+            let keep = offset.unsigned_abs() as usize;
+            let skip = self.updates.len().saturating_sub(keep);
+            self.updates.drain(..skip);
+        } else {
+            self.updates.retain(|u| u.id.0 as i64 >= offset as i64);
+        }
+    }
+
+    /// Telegram remembers the last `allowed_updates` that were passed, so this should be called
+    /// only if they were specified in the request
+    pub fn set_allowed_updates(&mut self, allowed_updates: Vec<AllowedUpdate>) {
+        // An empty list means "all updates except the ones that must be requested explicitly"
+        self.allowed_updates = Some(allowed_updates).filter(|a| !a.is_empty());
+    }
+
+    /// Returns up to `limit` pending updates without confirming them. Updates of types that were
+    /// not allowed are dropped, they will never be delivered.
+    pub fn get_updates(&mut self, limit: usize) -> Vec<Update> {
+        if let Some(allowed_updates) = &self.allowed_updates {
+            self.updates.retain(|update| {
+                update_type(update).map_or(true, |kind| allowed_updates.contains(&kind))
+            });
+        }
+        self.updates.iter().take(limit).cloned().collect()
+    }
+}
+
+fn update_type(update: &Update) -> Option<AllowedUpdate> {
+    // The update is serialized as `{"update_id": ..., "<update type>": ...}`, and the update type
+    // is named the same way as in `AllowedUpdate`
+    let value = serde_json::to_value(update).ok()?;
+    let kind = value
+        .as_object()?
+        .keys()
+        .find(|key| key.as_str() != "update_id")?
+        .clone();
+    serde_json::from_value(serde_json::Value::String(kind)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicI32;
+
+    use super::*;
+    use crate::dataset::*;
+
+    fn queue_with_updates() -> UpdateQueue {
+        let id = AtomicI32::new(1);
+        let mut queue = UpdateQueue::default();
+        for update in MockMessageText::new().into_update(&id) {
+            queue.push(update);
+        }
+        for update in MockCallbackQuery::new().into_update(&id) {
+            queue.push(update);
+        }
+        for update in MockMessageText::new().into_update(&id) {
+            queue.push(update);
+        }
+        queue
+    }
+
+    #[test]
+    fn test_confirm() {
+        let mut queue = queue_with_updates();
+        queue.confirm(2);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.get_updates(100)[0].id.0, 2);
+
+        queue.confirm(4);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_negative_offset() {
+        let mut queue = queue_with_updates();
+        queue.confirm(-1);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.get_updates(100)[0].id.0, 3);
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut queue = queue_with_updates();
+        assert_eq!(queue.get_updates(2).len(), 2);
+        // Getting the updates doesn't confirm them
+        assert_eq!(queue.len(), 3);
+    }
+
+    #[test]
+    fn test_allowed_updates() {
+        let mut queue = queue_with_updates();
+        queue.set_allowed_updates(vec![AllowedUpdate::CallbackQuery]);
+        let updates = queue.get_updates(100);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].id.0, 2);
+        // Not allowed updates are dropped
+        assert_eq!(queue.len(), 1);
+    }
+}
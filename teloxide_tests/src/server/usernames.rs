@@ -0,0 +1,212 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use actix_web::{
+    body::BoxBody,
+    dev::{ServiceRequest, ServiceResponse},
+    error::ResponseError,
+    middleware::Next,
+    web::{Bytes, Data},
+};
+use serde_json::Value;
+use teloxide::{
+    types::{Chat, ChatId},
+    ApiError,
+};
+
+use super::{
+    chats::CHAT_ID_FIELDS, request_body, routes::BotApiError, set_request_body, RawRequestBody,
+    RequestBody,
+};
+use crate::state::State;
+
+/// The usernames of the public chats, so the bots can write to `@username` instead of the chat id
+#[derive(Debug, Default)]
+pub struct Usernames {
+    chats: HashMap<String, ChatId>,
+}
+
+impl Usernames {
+    /// Makes the `username` (with or without the `@`) point to the chat
+    pub fn add(&mut self, username: &str, chat_id: ChatId) {
+        self.chats.insert(normalize(username), chat_id);
+    }
+
+    /// Returns the chat with the `username`, if there is one. Usernames don't care about the case
+    pub fn resolve(&self, username: &str) -> Option<ChatId> {
+        self.chats.get(&normalize(username)).copied()
+    }
+
+    /// Remembers the username of the chat the fake server has seen, if it is a public one. Only
+    /// the channels and the supergroups can be found by their usernames
+    pub(crate) fn add_chat(&mut self, chat: &Chat) {
+        if let (false, Some(username)) = (chat.is_private(), chat.username()) {
+            self.add(username, chat.id);
+        }
+    }
+
+    /// Turns the chat id of the request into a number. The numbers are left as they are
+    fn resolve_field(&self, value: &str) -> Result<i64, ApiError> {
+        if let Ok(id) = value.parse() {
+            return Ok(id);
+        }
+        value
+            .strip_prefix('@')
+            .and_then(|username| self.resolve(username))
+            .map(|chat_id| chat_id.0)
+            .ok_or(ApiError::ChatNotFound)
+    }
+}
+
+fn normalize(username: &str) -> String {
+    username.trim_start_matches('@').to_lowercase()
+}
+
+/// Replaces the text of the multipart field `name` with `new` in the raw body
+fn replace_multipart_field(raw_body: &[u8], name: &str, old: &str, new: &str) -> Option<Vec<u8>> {
+    let header = format!("name=\"{name}\"");
+    let header_start = find(raw_body, header.as_bytes())?;
+    let value_start = header_start + find(&raw_body[header_start..], b"\r\n\r\n")? + 4;
+    let value_end = value_start + old.len();
+    if raw_body.get(value_start..value_end)? != old.as_bytes() {
+        return None;
+    }
+    Some(
+        [
+            &raw_body[..value_start],
+            new.as_bytes(),
+            &raw_body[value_end..],
+        ]
+        .concat(),
+    )
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Returns the body with the ids of the chats in place of their usernames, or `None` if there
+/// were no usernames
+fn resolve_body(
+    usernames: &Usernames,
+    body: &RequestBody,
+) -> Result<Option<RequestBody>, ApiError> {
+    // The numbers in the multipart fields are already numbers in the JSON
+    if !CHAT_ID_FIELDS
+        .iter()
+        .any(|field| body.json[field].is_string())
+    {
+        return Ok(None);
+    }
+
+    let mut raw = body.raw.clone();
+    let mut bytes = body.bytes.clone();
+    for field in CHAT_ID_FIELDS {
+        let Some(value) = body.json[field].as_str() else {
+            continue;
+        };
+        let id = usernames.resolve_field(value)?;
+        match &mut raw {
+            RawRequestBody::Json(json) => json[field] = Value::from(id),
+            RawRequestBody::Multipart { fields, .. } => {
+                // The route can't find the chat by the username, so it is better to say so than
+                // to let the username through
+                let replaced = replace_multipart_field(&bytes, field, value, &id.to_string())
+                    .ok_or(ApiError::ChatNotFound)?;
+                bytes = Bytes::from(replaced);
+                fields.insert(field.to_string(), id.to_string());
+            }
+            RawRequestBody::Other(_) => {}
+        }
+    }
+    if let RawRequestBody::Json(json) = &raw {
+        bytes = Bytes::from(serde_json::to_vec(json).unwrap());
+    }
+    Ok(Some(RequestBody::new(bytes, raw)))
+}
+
+/// Puts the ids of the chats in place of their usernames, so the routes only see the numbers.
+/// Responds with `ChatNotFound` if there is no chat with such username
+pub(crate) async fn resolve_chat_ids(
+    mut req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let (Some(state), Some(body)) = (
+        req.app_data::<Data<Mutex<State>>>().cloned(),
+        request_body(&req),
+    ) else {
+        return next.call(req).await;
+    };
+
+    let resolved = resolve_body(&state.lock().unwrap().usernames, &body);
+    match resolved {
+        Ok(Some(new_body)) => {
+            set_request_body(&mut req, new_body);
+            next.call(req).await
+        }
+        Ok(None) => next.call(req).await,
+        Err(error) => Ok(req.into_response(BotApiError::new(error).error_response())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::{MockChannelChat, MockPrivateChat};
+
+    #[test]
+    fn test_resolve() {
+        let mut usernames = Usernames::default();
+        usernames.add_chat(&MockChannelChat::new().id(-1001).username("News").build());
+        // Private chats can't be written to by their usernames
+        usernames.add_chat(&MockPrivateChat::new().username("user").build());
+
+        assert_eq!(usernames.resolve_field("@news"), Ok(-1001));
+        assert_eq!(usernames.resolve_field("-1002"), Ok(-1002));
+        assert_eq!(
+            usernames.resolve_field("@user"),
+            Err(ApiError::ChatNotFound)
+        );
+        assert_eq!(usernames.resolve_field("news"), Err(ApiError::ChatNotFound));
+    }
+
+    #[test]
+    fn test_replace_multipart_field() {
+        let raw_body =
+            b"--b\r\nContent-Disposition: form-data; name=\"chat_id\"\r\n\r\n@news\r\n--b--";
+        let replaced = replace_multipart_field(raw_body, "chat_id", "@news", "-1001").unwrap();
+        assert_eq!(
+            replaced,
+            b"--b\r\nContent-Disposition: form-data; name=\"chat_id\"\r\n\r\n-1001\r\n--b--"
+        );
+        assert_eq!(
+            replace_multipart_field(raw_body, "from_chat_id", "@news", "-1001"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_resolve_multipart_body() {
+        let mut usernames = Usernames::default();
+        usernames.add("news", ChatId(-1001));
+        let body = |bytes: &'static [u8]| {
+            let raw = RawRequestBody::Multipart {
+                fields: [("chat_id".to_string(), "@news".to_string())].into(),
+                attachments: Default::default(),
+            };
+            RequestBody::new(Bytes::from_static(bytes), raw)
+        };
+        let raw_body =
+            b"--b\r\nContent-Disposition: form-data; name=\"chat_id\"\r\n\r\n@news\r\n--b--";
+
+        let resolved = resolve_body(&usernames, &body(raw_body)).unwrap().unwrap();
+        assert!(find(&resolved.bytes, b"-1001").is_some());
+        assert_eq!(resolved.json["chat_id"], -1001);
+        // The username can't be left in the body if it isn't where the parsed fields say it is
+        assert_eq!(
+            resolve_body(&usernames, &body(b"--b--")).err(),
+            Some(ApiError::ChatNotFound)
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+use teloxide::types::Update;
+
+use super::SetWebhookBody;
+
+/// How many times to try connecting to the webhook, in case it didn't bind to its port yet
+const CONNECT_ATTEMPTS: u32 = 100;
+
+/// Sends the update to the webhook the same way telegram does, returning the status code of the
+/// response
+pub(crate) async fn deliver_update(
+    client: &reqwest::Client,
+    webhook: &SetWebhookBody,
+    secret_token: Option<&str>,
+    update: &Update,
+) -> Result<u16, reqwest::Error> {
+    let mut attempt = 1;
+    loop {
+        let mut request = client
+            .post(&webhook.url)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(update).unwrap());
+        if let Some(secret_token) = secret_token {
+            request = request.header("X-Telegram-Bot-Api-Secret-Token", secret_token);
+        }
+
+        match request.send().await {
+            Ok(response) => return Ok(response.status().as_u16()),
+            Err(err) if err.is_connect() && attempt < CONNECT_ATTEMPTS => {
+                attempt += 1;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
@@ -1,25 +1,199 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
 use teloxide::{
     prelude::*,
-    types::{File, MessageId, MessageKind},
+    types::{
+        ChatPermissions, File, MaybeInaccessibleMessage, MediaKind, MediaPoll, MessageCommon,
+        MessageId, MessageKind, ReplyMarkup, UpdateKind,
+    },
 };
 
-use crate::{server::messages::Messages, utils::find_file, MockMessageText, Responses};
+use crate::{
+    server::{
+        chats::Chats,
+        clock::Clock,
+        faults::Fault,
+        flood_control::FloodControl,
+        messages::Messages,
+        permissions::{is_allowed, Permissions},
+        random::Random,
+        reply_keyboards::ReplyKeyboards,
+        stubs::Stub,
+        update_queue::UpdateQueue,
+        usernames::Usernames,
+        BotCallKind, SetWebhookBody,
+    },
+    utils::find_file,
+    MockMessageText, Responses,
+};
 
 #[derive(Default)]
 pub(crate) struct State {
     pub files: Vec<File>,
     pub responses: Responses,
     pub messages: Messages,
+    /// The currently set webhook, if any. Unlike responses, it lives as long as the bot
+    pub webhook: Option<SetWebhookBody>,
+    /// Updates waiting to be fetched with `getUpdates`
+    pub update_queue: UpdateQueue,
+    /// When the bot made its last request (long polling doesn't count). Used to tell when the
+    /// bot is done with its updates
+    pub last_request_at: Option<Instant>,
+    pub clock: Clock,
+    pub random: Random,
+    /// Banned users of the chats, with the time their ban ends at (`None` is forever)
+    pub banned_users: HashMap<(ChatId, UserId), Option<DateTime<Utc>>>,
+    /// Restricted users of the chats
+    pub restricted_users: HashMap<(ChatId, UserId), Restriction>,
+    /// Custom handlers of the endpoints by their method names. They are called instead of the
+    /// routes
+    pub stubs: HashMap<String, Stub>,
+    /// The panic of a stub, that is raised again in the test after the dispatch
+    pub stub_panic: Option<Box<dyn Any + Send>>,
+    /// Faults that are waiting for their calls
+    pub faults: Vec<Fault>,
+    /// The limits on sending messages, if they are turned on
+    pub flood_control: Option<FloodControl>,
+    pub reply_keyboards: ReplyKeyboards,
+    /// The chats and the users that appeared in the updates or were added by hand
+    pub chats: Chats,
+    /// The usernames of the public chats, that the bot can use instead of their ids
+    pub usernames: Usernames,
+    /// The rights of the bot in the chats
+    pub permissions: Permissions,
 }
 
+pub(crate) struct Restriction {
+    pub permissions: ChatPermissions,
+    /// The time the restriction ends at (`None` is forever)
+    pub until: Option<DateTime<Utc>>,
+}
+
+/// Bots can't delete messages that are older than this
+const DELETE_WINDOW: TimeDelta = TimeDelta::hours(48);
+
 impl State {
     pub fn reset(&mut self) {
         self.responses = Responses::default();
     }
 
+    /// Inserts the messages of the update into the state, so the bot can interact with them.
+    /// Returns `false` if the update can't happen, because its user is banned or restricted in
+    /// the chat, and it shouldn't reach the bot
+    pub(crate) fn insert_update(&mut self, update: &mut Update) -> bool {
+        if let UpdateKind::Message(message) = &update.kind {
+            if !self.can_send(message) {
+                log::debug!(
+                    "Not inserting message with id {}, its user can't send it.",
+                    message.id
+                );
+                return false;
+            }
+        }
+        if let Some(chat) = update.chat() {
+            self.usernames.add_chat(chat);
+        }
+        self.chats.add_update(update);
+        match update.kind.clone() {
+            UpdateKind::Message(mut message) => {
+                // Add the message to the list of messages, so the bot can interact with it
+                self.add_message(&mut message);
+                self.reply_keyboards.user_sent(&message);
+                update.kind = UpdateKind::Message(message.clone());
+            }
+            UpdateKind::EditedMessage(mut message) => {
+                self.edit_message(&mut message);
+                update.kind = UpdateKind::EditedMessage(message.clone());
+            }
+            UpdateKind::CallbackQuery(mut callback) => {
+                if let Some(MaybeInaccessibleMessage::Regular(ref mut message)) = callback.message {
+                    self.add_referred_message(message);
+                }
+                update.kind = UpdateKind::CallbackQuery(callback.clone());
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Shows the reply keyboard or the force reply of the message the bot has just sent
+    pub(crate) fn show_reply_markup(
+        &mut self,
+        message: &Message,
+        reply_markup: Option<&ReplyMarkup>,
+    ) {
+        self.reply_keyboards
+            .show(message, reply_markup, &self.chats);
+    }
+
+    /// Records a call of the bot in the responses, at the current time of the clock
+    pub(crate) fn record(&mut self, kind: BotCallKind) {
+        let now = self.clock.now();
+        self.responses.record(kind, now);
+    }
+
+    /// Moves the clock forward, closing the polls that ran out of time
+    pub(crate) fn advance_time(&mut self, duration: Duration) {
+        self.clock.advance(duration);
+        let now = self.clock.now();
+        for message in self.messages.iter_mut() {
+            if let MessageKind::Common(MessageCommon {
+                media_kind: MediaKind::Poll(MediaPoll { poll, .. }),
+                ..
+            }) = &mut message.kind
+            {
+                if poll.close_date.is_some_and(|close_date| close_date <= now) {
+                    poll.is_closed = true;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn is_banned(&self, chat_id: ChatId, user_id: UserId) -> bool {
+        self.banned_users
+            .get(&(chat_id, user_id))
+            .is_some_and(|until| self.clock.is_before(*until))
+    }
+
+    /// Returns the permissions of the user, if they are still restricted
+    pub(crate) fn restrictions(&self, chat_id: ChatId, user_id: UserId) -> Option<ChatPermissions> {
+        let restriction = self.restricted_users.get(&(chat_id, user_id))?;
+        self.clock
+            .is_before(restriction.until)
+            .then(|| restriction.permissions.clone())
+    }
+
+    /// If the user of the message isn't banned in the chat, and their restrictions allow them to
+    /// send it
+    pub(crate) fn can_send(&self, message: &Message) -> bool {
+        let Some(user) = &message.from else {
+            return true;
+        };
+        if self.is_banned(message.chat.id, user.id) {
+            return false;
+        }
+        self.restrictions(message.chat.id, user.id)
+            .map_or(true, |permissions| is_allowed(&permissions, message))
+    }
+
+    /// Bots can delete only the messages that were sent less than 48 hours ago. Outside of the
+    /// private chats, the messages of the others need the right to delete them
+    pub(crate) fn can_be_deleted(&self, message: &Message, bot_id: UserId) -> bool {
+        let is_own = message.from.as_ref().is_some_and(|user| user.id == bot_id);
+        let has_rights = is_own
+            || message.chat.is_private()
+            || self.permissions.can_delete_messages(message.chat.id);
+        has_rights && self.clock.now() - message.date < DELETE_WINDOW
+    }
+
     pub(crate) fn add_message(&mut self, message: &mut Message) {
-        let max_id = self.messages.max_message_id();
-        let maybe_message = self.messages.get_message(message.id.0);
+        let chat_id = message.chat.id;
+        let maybe_message = self.messages.get_message(chat_id, message.id.0);
 
         // If message exists in the database, and it isn't a default,
         // let it be, the user knows best
@@ -31,9 +205,18 @@ impl State {
             return;
         }
 
-        if message.id.0 <= max_id || maybe_message.is_some() {
+        // The message that is replied to was sent earlier, so it goes first and gets a smaller id
+        if let MessageKind::Common(ref mut message_kind) = message.kind {
+            if let Some(ref mut reply_message) = message_kind.reply_to_message {
+                self.add_referred_message(reply_message);
+            }
+        }
+
+        let max_id = self.messages.max_message_id(chat_id);
+        if message.id.0 <= max_id || self.messages.get_message(chat_id, message.id.0).is_some() {
             message.id = MessageId(max_id + 1);
         }
+        message.date = self.clock.shift(message.date);
 
         if let Some(file_meta) = find_file(serde_json::to_value(&message).unwrap()) {
             let file = File {
@@ -42,17 +225,25 @@ impl State {
             };
             self.files.push(file);
         }
-        if let MessageKind::Common(ref mut message_kind) = message.kind {
-            if let Some(ref mut reply_message) = message_kind.reply_to_message {
-                self.add_message(reply_message);
-            }
-        }
         log::debug!("Inserted message with {}.", message.id);
         self.messages.add_message(message.clone());
     }
 
+    /// Adds the message that the update only refers to, like the one with the pressed button or
+    /// the one that is replied to. If the chat already has it, it is the same message, so it is
+    /// neither renumbered nor added again
+    fn add_referred_message(&mut self, message: &mut Message) {
+        if self
+            .messages
+            .get_message(message.chat.id, message.id.0)
+            .is_none()
+        {
+            self.add_message(message);
+        }
+    }
+
     pub(crate) fn edit_message(&mut self, message: &mut Message) {
-        let old_message = self.messages.get_message(message.id.0);
+        let old_message = self.messages.get_message(message.chat.id, message.id.0);
 
         if old_message.is_none() {
             log::error!(
@@ -62,6 +253,12 @@ impl State {
             return;
         }
 
+        // The edits are moved to the time of the clock, just like the new messages
+        message.date = self.clock.shift(message.date);
+        if let MessageKind::Common(ref mut common) = message.kind {
+            common.edit_date = common.edit_date.map(|date| self.clock.shift(date));
+        }
+
         if let Some(file_meta) = find_file(serde_json::to_value(&message).unwrap()) {
             if self
                 .files
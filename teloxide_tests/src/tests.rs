@@ -7,24 +7,32 @@ use std::{
 use chrono::Utc;
 use futures_util::future::BoxFuture;
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "webhooks")]
+use teloxide::update_listeners::webhooks;
 use teloxide::{
     dispatching::{
         dialogue::{self, serializer::Json, ErasedStorage, InMemStorage, SqliteStorage, Storage},
-        HandlerExt, UpdateFilterExt, UpdateHandler,
+        DefaultKey, HandlerExt, UpdateFilterExt, UpdateHandler,
     },
     dptree::{case, deps},
     error_handlers::ErrorHandler,
     macros::BotCommands,
     net::Download,
-    payloads::{BanChatMemberSetters, CopyMessageSetters, SendPhotoSetters, SendPollSetters},
+    payloads::{
+        BanChatMemberSetters, CopyMessageSetters, RestrictChatMemberSetters, SendMessageSetters,
+        SendPhotoSetters, SendPollSetters,
+    },
     prelude::*,
     requests::Requester,
     sugar::request::RequestReplyExt,
     types::{
-        BotCommand, ChatAction, ChatPermissions, DiceEmoji, InlineKeyboardButton,
-        InlineKeyboardMarkup, InputFile, InputMedia, InputMediaAudio, InputMediaDocument,
-        InputMediaPhoto, InputMediaVideo, LabeledPrice, LinkPreviewOptions, Message, MessageEntity,
-        MessageId, PollOption, PollType, ReactionType, ReplyParameters, Update,
+        Administrator, AllowedUpdate, BotCommand, ButtonRequest, ChatAction, ChatMemberKind,
+        ChatPermissions, DiceEmoji, InlineKeyboardButton, InlineKeyboardMarkup, InputFile,
+        InputMedia, InputMediaAudio, InputMediaDocument, InputMediaPhoto, InputMediaVideo,
+        KeyboardButton, KeyboardButtonPollType, KeyboardButtonRequestChat,
+        KeyboardButtonRequestUsers, KeyboardMarkup, LabeledPrice, LinkPreviewOptions, Member,
+        Message, MessageEntity, MessageId, MessageKind, PollOption, PollType, ReactionType,
+        Recipient, ReplyMarkup, ReplyParameters, RequestId, Update,
     },
 };
 
@@ -241,6 +249,8 @@ pub enum AllCommands {
     #[command()]
     SetMyCommands,
     #[command()]
+    Webhook,
+    #[command()]
     Panic,
 }
 
@@ -485,6 +495,24 @@ async fn handler(
             }])
             .await?;
         }
+        AllCommands::Webhook => {
+            let url = reqwest::Url::parse("https://example.com/webhook").unwrap();
+            bot.set_webhook(url.clone())
+                .secret_token("secret")
+                .max_connections(10)
+                .allowed_updates(vec![AllowedUpdate::Message])
+                .await?;
+            let webhook_info = bot.get_webhook_info().await?;
+            assert_eq!(webhook_info.url, Some(url));
+            assert_eq!(webhook_info.max_connections, Some(10));
+            assert_eq!(
+                webhook_info.allowed_updates,
+                Some(vec![AllowedUpdate::Message])
+            );
+
+            bot.delete_webhook().drop_pending_updates(true).await?;
+            assert_eq!(bot.get_webhook_info().await?.url, None);
+        }
         AllCommands::Panic => {
             // This message id does not exist
             bot.send_message(msg.chat.id, "test")
@@ -609,7 +637,7 @@ async fn test_error_handler() {
 
     let errors = error_handler.errors();
     assert_eq!(errors.len(), 1);
-    assert!(errors[0].contains("Message not found"));
+    assert!(errors[0].contains("MessageToReplyNotFound"));
 }
 
 #[tokio::test]
@@ -625,6 +653,149 @@ async fn test_no_updates() {
     bot.dispatch_and_check_last_text("/echo echo").await;
 }
 
+fn polling_dispatcher(
+) -> Dispatcher<Bot, Box<dyn std::error::Error + Send + Sync + 'static>, DefaultKey> {
+    // Just like a regular main function of a bot, before it calls `dispatch()`
+    let bot = Bot::from_env();
+    Dispatcher::builder(bot, get_schema()).build()
+}
+
+#[tokio::test]
+async fn test_dispatch_polling() {
+    let mut bot = MockBot::new(
+        vec![
+            MockMessageText::new().text("/echo first"),
+            MockMessageText::new().text("/echo second"),
+        ],
+        get_schema(),
+    );
+
+    bot.dispatch_polling(polling_dispatcher).await;
+
+    let responses = bot.get_responses();
+    assert_eq!(responses.sent_messages.len(), 2);
+    assert_eq!(responses.sent_messages[0].text(), Some("/echo first"));
+    assert_eq!(responses.sent_messages[1].text(), Some("/echo second"));
+
+    // The same bot can be polled again with new updates
+    bot.update(MockMessageText::new().text("/echo third"));
+    bot.dispatch_polling(polling_dispatcher).await;
+
+    let responses = bot.get_responses();
+    assert_eq!(responses.sent_messages.len(), 1);
+    assert_eq!(responses.sent_messages[0].text(), Some("/echo third"));
+}
+
+fn slow_polling_dispatcher() -> Dispatcher<Bot, teloxide::RequestError, DefaultKey> {
+    let handler = Update::filter_message().endpoint(|bot: Bot, msg: Message| async move {
+        // Longer than the idle time of the dispatch
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        bot.send_message(msg.chat.id, "done").await?;
+        Ok::<_, teloxide::RequestError>(())
+    });
+    Dispatcher::builder(Bot::from_env(), handler).build()
+}
+
+#[tokio::test]
+async fn test_dispatch_polling_slow_handler() {
+    let mut bot = MockBot::new(MockMessageText::new(), get_schema());
+
+    bot.dispatch_polling(slow_polling_dispatcher).await;
+
+    let responses = bot.get_responses();
+    assert_eq!(responses.sent_messages.len(), 1);
+    assert_eq!(responses.sent_messages[0].text(), Some("done"));
+}
+
+#[tokio::test]
+async fn test_dispatch_polling_ignored_updates() {
+    let mut bot = MockBot::new(
+        vec![MockMessageText::new().text("not a command"); 3],
+        get_schema(),
+    );
+
+    let started_at = std::time::Instant::now();
+    bot.dispatch_polling(polling_dispatcher).await;
+
+    // Nobody waits for the updates that the bot has nothing to say about
+    assert!(started_at.elapsed() < std::time::Duration::from_secs(2));
+    assert!(bot.get_responses().sent_messages.is_empty());
+}
+
+#[tokio::test]
+async fn test_dispatch_polling_without_updates() {
+    let empty: Vec<MockMessageText> = vec![];
+    let mut bot = MockBot::new(empty, get_schema());
+
+    // The bot has nothing to do, so it just starts and stops
+    bot.dispatch_polling(polling_dispatcher).await;
+
+    assert!(bot.get_responses().sent_messages.is_empty());
+}
+
+#[tokio::test]
+#[should_panic(expected = "The bot didn't fetch 1 of its updates")]
+async fn test_dispatch_polling_timeout() {
+    let mut bot = MockBot::new(MockMessageText::new(), get_schema());
+    bot.dispatch_timeout = std::time::Duration::from_millis(500);
+    // The bot polls, but never gets its update
+    bot.stub_endpoint("GetUpdates", |_, _| Ok(serde_json::json!([])));
+
+    bot.dispatch_polling(polling_dispatcher).await;
+}
+
+#[cfg(feature = "webhooks")]
+fn webhook_options(secret_token: &str) -> webhooks::Options {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let address = ([127, 0, 0, 1], port).into();
+    let url = format!("http://127.0.0.1:{port}/webhook").parse().unwrap();
+    webhooks::Options::new(address, url).secret_token(secret_token.to_string())
+}
+
+#[cfg(feature = "webhooks")]
+#[tokio::test]
+async fn test_dispatch_webhook() {
+    let mut bot = MockBot::new(
+        vec![
+            MockMessageText::new().text("/echo first"),
+            MockMessageText::new().text("/echo second"),
+        ],
+        get_schema(),
+    );
+
+    bot.dispatch_webhook(webhook_options("secret")).await;
+
+    let responses = bot.get_responses();
+    assert_eq!(responses.set_webhooks.len(), 1);
+    assert_eq!(
+        responses.set_webhooks[0].secret_token,
+        Some("secret".to_string())
+    );
+    assert_eq!(responses.delivered_updates.len(), 2);
+    assert!(responses.delivered_updates.iter().all(|d| d.status == 200));
+    assert_eq!(responses.sent_messages.len(), 2);
+    assert_eq!(responses.sent_messages[0].text(), Some("/echo first"));
+    assert_eq!(responses.sent_messages[1].text(), Some("/echo second"));
+}
+
+#[cfg(feature = "webhooks")]
+#[tokio::test]
+async fn test_dispatch_webhook_wrong_secret_token() {
+    let mut bot = MockBot::new(MockMessageText::new().text("/echo echo"), get_schema());
+    bot.webhook_secret_token("wrong_secret");
+
+    bot.dispatch_webhook(webhook_options("secret")).await;
+
+    let responses = bot.get_responses();
+    assert_eq!(responses.delivered_updates.len(), 1);
+    assert_eq!(responses.delivered_updates[0].status, 401);
+    assert!(responses.sent_messages.is_empty());
+}
+
 #[tokio::test]
 async fn test_send_photo() {
     let mut bot = MockBot::new(MockMessageText::new().text("/photo"), get_schema());
@@ -1223,6 +1394,58 @@ async fn test_set_my_commands() {
     );
 }
 
+#[tokio::test]
+async fn test_webhook() {
+    let mut bot = MockBot::new(MockMessageText::new().text("/webhook"), get_schema());
+    let error_handler = Arc::new(MyErrorHandler::new());
+    bot.error_handler(error_handler.clone());
+
+    bot.dispatch().await;
+
+    assert!(error_handler.errors().is_empty());
+    let responses = bot.get_responses();
+    let set_webhook = responses.set_webhooks.last().unwrap();
+    let deleted_webhook = responses.deleted_webhooks.last().unwrap();
+
+    assert_eq!(set_webhook.url, "https://example.com/webhook");
+    assert_eq!(set_webhook.secret_token, Some("secret".to_string()));
+    assert_eq!(set_webhook.max_connections, Some(10));
+    assert_eq!(deleted_webhook.drop_pending_updates, Some(true));
+}
+
+async fn webhook_certificate_handler(bot: Bot, _: Message) -> ResponseResult<()> {
+    let url = reqwest::Url::parse("https://example.com/webhook").unwrap();
+    let certificate = InputFile::memory("certificate".to_string()).file_name("cert.pem");
+    bot.set_webhook(url).certificate(certificate).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_webhook_certificate_and_json() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(webhook_certificate_handler),
+    );
+
+    bot.dispatch().await;
+    // The other libraries send the webhook without a certificate as JSON
+    let api_url = std::env::var("TELOXIDE_API_URL").unwrap();
+    let token = std::env::var("TELOXIDE_TOKEN").unwrap();
+    let response = reqwest::Client::new()
+        .post(format!("{api_url}bot{token}/setWebhook"))
+        .json(&serde_json::json!({ "url": "https://example.com/json", "max_connections": 5 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let set_webhooks = bot.get_responses().set_webhooks;
+    assert_eq!(set_webhooks[0].certificate, Some("cert.pem".to_string()));
+    assert_eq!(set_webhooks[1].url, "https://example.com/json");
+    assert_eq!(set_webhooks[1].max_connections, Some(5));
+    assert_eq!(set_webhooks[1].certificate, None);
+}
+
 #[tokio::test]
 async fn test_send_invoice() {
     let mut bot = MockBot::new(MockMessageText::new().text("/invoice"), get_schema());
@@ -1263,3 +1486,1366 @@ async fn test_edited_message() {
     let forwarded_message = &responses.forwarded_messages[0].message;
     assert_eq!(forwarded_message.text(), Some("/forwardmessage second"));
 }
+
+#[tokio::test]
+async fn test_standalone_server() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let server = tokio::spawn(server::run_standalone(
+        ("127.0.0.1", port),
+        MockMe::new().build(),
+    ));
+    let address = format!("http://127.0.0.1:{port}");
+    let client = reqwest::Client::new();
+
+    // Waits until the server is ready
+    let update = &MockMessageText::new().text("hi").into_update(&1.into())[0];
+    let mut attempts = 0;
+    let response = loop {
+        match client
+            .post(format!("{address}/admin/updates"))
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(update).unwrap())
+            .send()
+            .await
+        {
+            Ok(response) => break response,
+            Err(_) if attempts < 100 => attempts += 1,
+            Err(err) => panic!("{err}"),
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    };
+    assert_eq!(response.status(), 200);
+
+    let bot = Bot::new("1234567890:QWERTYUIOPASDFGHJKLZXCVBNMQWERTYUIO")
+        .set_api_url(address.parse().unwrap());
+    let updates = bot.get_updates().await.unwrap();
+    assert_eq!(updates.len(), 1);
+    let Some(chat) = updates[0].chat() else {
+        panic!("The update has no chat");
+    };
+    bot.send_message(chat.id, "hello").await.unwrap();
+
+    let responses: serde_json::Value = client
+        .get(format!("{address}/admin/responses"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(responses["sent_messages"][0]["text"], "hello");
+    assert_eq!(
+        responses["sent_messages_text"][0]["bot_request"]["text"],
+        "hello"
+    );
+
+    let messages: serde_json::Value = client
+        .get(format!("{address}/admin/messages"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(messages.as_array().unwrap().len(), 2);
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn test_server_is_reused_between_dispatches() {
+    let mut bot = MockBot::new(MockMessageText::new().text("/echo first"), get_schema());
+
+    bot.dispatch().await;
+    let api_url = std::env::var("TELOXIDE_API_URL").unwrap();
+
+    bot.update(MockMessageText::new().text("/echo second"));
+    bot.dispatch().await;
+    assert_eq!(std::env::var("TELOXIDE_API_URL").unwrap(), api_url);
+    let responses = bot.get_responses();
+    assert_eq!(responses.sent_messages.len(), 1);
+    assert_eq!(responses.sent_messages[0].text(), Some("/echo second"));
+
+    // `me` is a part of the server, so it has to restart with the new one
+    bot.me(MockMe::new().first_name("Changed"));
+    bot.update(MockMessageText::new().text("/echo third"));
+    bot.dispatch().await;
+    let responses = bot.get_responses();
+    assert_eq!(
+        responses.sent_messages[0].from.as_ref().unwrap().first_name,
+        "Changed"
+    );
+}
+
+#[tokio::test]
+async fn test_use_current_runtime_paused_time() {
+    tokio::time::pause();
+    let handler_tree = Update::filter_message().endpoint(|message: Message, bot: Bot| async move {
+        let start = tokio::time::Instant::now();
+        tokio::time::sleep(std::time::Duration::from_secs(60 * 60)).await;
+        let slept = start.elapsed().as_secs();
+        bot.send_message(message.chat.id, slept.to_string()).await?;
+        Ok::<(), Box<dyn std::error::Error + Send + Sync>>(())
+    });
+    let mut bot = MockBot::new(MockMessageText::new(), handler_tree);
+    bot.use_current_runtime = true;
+
+    let started = std::time::Instant::now();
+    bot.dispatch().await;
+
+    // The clock is paused, so the hour has passed instantly
+    assert!(started.elapsed().as_secs() < 60);
+    let responses = bot.get_responses();
+    assert_eq!(responses.sent_messages.last().unwrap().text(), Some("3600"));
+}
+
+async fn time_handler(
+    bot: Bot,
+    msg: Message,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+    let user_id = msg.from.clone().unwrap().id;
+    let in_an_hour = msg.date + chrono::TimeDelta::hours(1);
+    match msg.text().unwrap().split_once(' ') {
+        None if msg.text() == Some("/send") => {
+            bot.send_message(msg.chat.id, "sent").await?;
+        }
+        None if msg.text() == Some("/ban") => {
+            bot.ban_chat_member(msg.chat.id, user_id)
+                .until_date(in_an_hour)
+                .await?;
+        }
+        None if msg.text() == Some("/restrict") => {
+            bot.restrict_chat_member(msg.chat.id, user_id, ChatPermissions::empty())
+                .until_date(in_an_hour)
+                .await?;
+        }
+        None if msg.text() == Some("/poll") => {
+            bot.send_poll(msg.chat.id, "Question", vec!["1".into(), "2".into()])
+                .open_period(60)
+                .await?;
+        }
+        Some(("/delete", id)) => {
+            let result = bot
+                .delete_message(msg.chat.id, MessageId(id.parse()?))
+                .await;
+            let text = if result.is_ok() {
+                "deleted"
+            } else {
+                "can't delete"
+            };
+            bot.send_message(msg.chat.id, text).await?;
+        }
+        Some(("/forward", id)) => {
+            bot.forward_message(msg.chat.id, msg.chat.id, MessageId(id.parse()?))
+                .await?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn get_time_schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
+    Update::filter_message().endpoint(time_handler)
+}
+
+#[tokio::test]
+async fn test_advance_time_dates_and_delete_window() {
+    let mut bot = MockBot::new(MockMessageText::new().text("/send"), get_time_schema());
+    bot.dispatch().await;
+    let sent_message = bot.get_responses().sent_messages.pop().unwrap();
+
+    bot.advance_time(std::time::Duration::from_secs(60 * 60 * 49));
+    bot.update(MockMessageText::new().text(format!("/delete {}", sent_message.id)));
+    bot.dispatch().await;
+
+    let responses = bot.get_responses();
+    let last_message = responses.sent_messages.last().unwrap();
+    assert_eq!(last_message.text(), Some("can't delete"));
+    assert!(last_message.date - sent_message.date >= chrono::TimeDelta::hours(49));
+    assert!(responses.deleted_messages.is_empty());
+}
+
+#[tokio::test]
+async fn test_advance_time_edited_message() {
+    let date = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let message = MockMessageText::new().text("first").date(date);
+    let mut bot = MockBot::new(message.clone(), get_time_schema());
+    bot.advance_time(std::time::Duration::from_secs(60 * 60));
+    bot.dispatch().await;
+
+    bot.update(MockEditedMessage::new(
+        message.text("second").edit_date(date).build(),
+    ));
+    bot.dispatch().await;
+
+    let chat_history = bot.chat_history(ChatId(MockUser::ID as i64));
+    let edited_message = &chat_history[0];
+    assert_eq!(edited_message.text(), Some("second"));
+    // The edits are moved forward just like the messages
+    assert_eq!(edited_message.date, date + chrono::TimeDelta::hours(1));
+    assert_eq!(
+        edited_message.edit_date(),
+        Some(&(date + chrono::TimeDelta::hours(1)))
+    );
+}
+
+#[tokio::test]
+async fn test_advance_time_ban_and_restriction() {
+    let chat_id = ChatId(MockUser::ID as i64);
+    let user_id = UserId(MockUser::ID);
+    let mut bot = MockBot::new(
+        vec![
+            MockMessageText::new().text("/ban"),
+            MockMessageText::new().text("/restrict"),
+        ],
+        get_time_schema(),
+    );
+
+    bot.dispatch().await;
+    assert!(bot.is_banned(chat_id, user_id));
+    assert_eq!(
+        bot.restrictions(chat_id, user_id),
+        Some(ChatPermissions::empty())
+    );
+
+    bot.advance_time(std::time::Duration::from_secs(60 * 60 * 2));
+    assert!(!bot.is_banned(chat_id, user_id));
+    assert_eq!(bot.restrictions(chat_id, user_id), None);
+}
+
+#[tokio::test]
+async fn test_advance_time_closes_polls() {
+    let mut bot = MockBot::new(MockMessageText::new().text("/poll"), get_time_schema());
+    bot.dispatch().await;
+    let poll_message = bot.get_responses().sent_messages.pop().unwrap();
+    assert!(!poll_message.poll().unwrap().is_closed);
+
+    bot.advance_time(std::time::Duration::from_secs(61));
+    bot.update(MockMessageText::new().text(format!("/forward {}", poll_message.id)));
+    bot.dispatch().await;
+
+    let forwarded_message = bot.get_responses().sent_messages.pop().unwrap();
+    assert!(forwarded_message.poll().unwrap().is_closed);
+}
+
+async fn run_deterministic_bot() -> String {
+    let time = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let mut bot = MockBot::new(
+        vec![
+            MockMessageText::new().text("/photo"),
+            MockMessageText::new().text("/mediagroup"),
+            MockMessageText::new().text("/dice"),
+        ],
+        get_schema(),
+    );
+    bot.seed(42);
+    bot.set_time(time);
+    bot.dispatch().await;
+    serde_json::to_string(&bot.get_responses()).unwrap()
+}
+
+#[tokio::test]
+async fn test_seed_and_set_time() {
+    let first = run_deterministic_bot().await;
+    let second = run_deterministic_bot().await;
+    assert_eq!(first, second);
+}
+
+async fn timeline_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    let first = bot.send_message(msg.chat.id, "first").await?;
+    let second = bot.send_message(msg.chat.id, "second").await?;
+    bot.send_chat_action(msg.chat.id, ChatAction::Typing)
+        .await?;
+    bot.edit_message_text(msg.chat.id, first.id, "edited")
+        .await?;
+    bot.delete_message(msg.chat.id, second.id).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_timeline() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(timeline_handler),
+    );
+
+    bot.dispatch().await;
+
+    let timeline = bot.get_responses().timeline;
+    assert_eq!(timeline.len(), 5);
+    for (i, call) in timeline.iter().enumerate() {
+        assert_eq!(call.index, i);
+    }
+    assert!(timeline
+        .windows(2)
+        .all(|calls| calls[0].date <= calls[1].date));
+    assert!(matches!(
+        &timeline[0].kind,
+        server::BotCallKind::SentMessageText(sent) if sent.message.text() == Some("first")
+    ));
+    assert!(matches!(
+        timeline[2].kind,
+        server::BotCallKind::SentChatAction(_)
+    ));
+    assert!(matches!(
+        &timeline[3].kind,
+        server::BotCallKind::EditedMessageText(edited) if edited.message.text() == Some("edited")
+    ));
+    assert!(matches!(
+        timeline[4].kind,
+        server::BotCallKind::DeletedMessage(_)
+    ));
+}
+
+async fn raw_requests_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    bot.send_message(msg.chat.id, "text").await?;
+    let photo = InputFile::memory("somedata".to_string()).file_name("test.jpg");
+    bot.send_photo(msg.chat.id, photo)
+        .caption("caption")
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_raw_requests() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(raw_requests_handler),
+    );
+
+    bot.dispatch().await;
+    // Teloxide waits for 10 seconds after server errors, so the unknown endpoint is called
+    // directly, the way a bot in any other language would
+    let api_url = std::env::var("TELOXIDE_API_URL").unwrap();
+    let token = std::env::var("TELOXIDE_TOKEN").unwrap();
+    reqwest::Client::new()
+        .post(format!("{api_url}bot{token}/getChatMenuButton"))
+        .json(&serde_json::json!({ "chat_id": 123 }))
+        .send()
+        .await
+        .unwrap();
+
+    let raw_requests = bot.get_responses().raw_requests;
+    let find = |method: &str| {
+        raw_requests
+            .iter()
+            .find(|request| request.method == method)
+            .unwrap()
+            .clone()
+    };
+
+    let send_message = find("SendMessage");
+    assert_eq!(send_message.status, 200);
+    let server::RawRequestBody::Json(body) = send_message.body else {
+        panic!("SendMessage should be sent as JSON");
+    };
+    assert_eq!(body["text"], "text");
+    let response: serde_json::Value = serde_json::from_str(&send_message.response).unwrap();
+    assert_eq!(response["result"]["text"], "text");
+
+    let send_photo = find("SendPhoto");
+    let server::RawRequestBody::Multipart {
+        fields,
+        attachments,
+    } = send_photo.body
+    else {
+        panic!("SendPhoto should be sent as multipart");
+    };
+    assert_eq!(fields["caption"], "caption");
+    assert_eq!(attachments["test.jpg"].file_data, "somedata");
+
+    let unknown = find("GetChatMenuButton");
+    assert!(matches!(
+        unknown.body,
+        server::RawRequestBody::Json(body) if body["chat_id"] == 123
+    ));
+    assert_eq!(unknown.status, 500);
+    assert!(unknown.response.contains("is not yet implemented"));
+}
+
+async fn stub_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    let menu_button = bot.get_chat_menu_button().chat_id(msg.chat.id).await?;
+    bot.send_message(msg.chat.id, format!("{menu_button:?}"))
+        .await?;
+    if let Err(err) = bot.send_dice(msg.chat.id).await {
+        bot.send_message(msg.chat.id, err.to_string()).await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stub_endpoint() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(stub_handler),
+    );
+    bot.stub_endpoint("getChatMenuButton", |request, state| {
+        assert_eq!(request["chat_id"], MockUser::ID);
+        // The stubs can see the state of the fake server
+        assert_eq!(state.messages.iter().count(), 1);
+        Ok(serde_json::to_value(teloxide::types::MenuButton::Commands).unwrap())
+    });
+    // Built-in routes can be overridden too
+    bot.stub_endpoint("SendDice", |_, _| Err(teloxide::ApiError::BotBlocked));
+
+    bot.dispatch().await;
+
+    let responses = bot.get_responses();
+    assert_eq!(responses.sent_messages[0].text(), Some("Commands"));
+    assert_eq!(
+        responses.sent_messages[1].text(),
+        Some(
+            teloxide::RequestError::Api(teloxide::ApiError::BotBlocked)
+                .to_string()
+                .as_str()
+        )
+    );
+    assert!(responses.sent_messages_dice.is_empty());
+}
+
+#[tokio::test]
+async fn test_panicking_stub() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(stub_handler),
+    );
+    bot.stub_endpoint("getChatMenuButton", |_, _| {
+        Ok(serde_json::to_value(teloxide::types::MenuButton::Commands).unwrap())
+    });
+    bot.stub_endpoint("SendDice", |request, _| {
+        assert_eq!(request["emoji"], "🎯", "Wrong emoji");
+        Ok(serde_json::Value::Null)
+    });
+
+    let panic = futures_util::FutureExt::catch_unwind(std::panic::AssertUnwindSafe(bot.dispatch()))
+        .await
+        .unwrap_err();
+
+    // The test fails with the panic of the stub once the dispatch is over
+    assert!(panic
+        .downcast_ref::<String>()
+        .unwrap()
+        .contains("Wrong emoji"));
+    // Until then, the panic is answered with a server error, and the fake server keeps working
+    let responses = bot.get_responses();
+    assert_eq!(responses.sent_messages.len(), 2);
+    let error = responses.sent_messages[1].text().unwrap();
+    assert!(error.contains("The stub of SendDice panicked"));
+    assert!(error.contains("Wrong emoji"));
+}
+
+async fn fault_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    let mut results = vec![];
+    for chat_id in [ChatId(42), msg.chat.id, ChatId(42)] {
+        let result = match bot.send_message(chat_id, "hi").await {
+            Ok(_) => "ok".to_string(),
+            Err(teloxide::RequestError::Api(err)) => format!("{err:?}"),
+            Err(teloxide::RequestError::Network(_)) => "network".to_string(),
+            Err(err) => err.to_string(),
+        };
+        results.push(result);
+    }
+    bot.send_message(msg.chat.id, results.join(" ")).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_inject_fault() {
+    use server::faults::{Fault, FaultKind};
+
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(fault_handler),
+    );
+    bot.inject_fault(
+        Fault::new(
+            "sendMessage",
+            FaultKind::Api(teloxide::ApiError::BotBlocked),
+        )
+        .chat_id(ChatId(42)),
+    );
+    bot.dispatch_and_check_last_text("BotBlocked ok ok").await;
+
+    // The fault is used up, and the drop counts only the calls after it was added
+    bot.inject_fault(Fault::new("SendMessage", FaultKind::DropConnection).nth(2));
+    bot.dispatch_and_check_last_text("ok network ok").await;
+    let dropped = bot
+        .get_responses()
+        .raw_requests
+        .into_iter()
+        .filter(|request| request.method == "SendMessage" && request.response.is_empty())
+        .count();
+    assert_eq!(dropped, 1);
+}
+
+#[tokio::test]
+async fn test_inject_fault_status() {
+    use server::faults::{Fault, FaultKind};
+
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(fault_handler),
+    );
+    bot.inject_fault(Fault::new("EditMessageText", FaultKind::Status(502)).nth(3));
+    bot.dispatch().await;
+
+    // Teloxide waits for 10 seconds after server errors, so the server is called directly
+    let api_url = std::env::var("TELOXIDE_API_URL").unwrap();
+    let token = std::env::var("TELOXIDE_TOKEN").unwrap();
+    let client = reqwest::Client::new();
+    let mut statuses = vec![];
+    for _ in 0..4 {
+        let response = client
+            .post(format!("{api_url}bot{token}/editMessageText"))
+            .json(&serde_json::json!({ "chat_id": 42, "message_id": 404, "text": "edited" }))
+            .send()
+            .await
+            .unwrap();
+        statuses.push(response.status().as_u16());
+        if response.status() == 502 {
+            let body: serde_json::Value = response.json().await.unwrap();
+            assert_eq!(body["ok"], false);
+            assert_eq!(body["error_code"], 502);
+            assert_eq!(body["description"], "Bad Gateway");
+        }
+    }
+    assert_eq!(statuses, vec![400, 400, 502, 400]);
+}
+
+async fn flood_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    bot.send_message(msg.chat.id, "first").await?;
+    let text = match bot.send_message(msg.chat.id, "second").await {
+        Ok(_) => "sent".to_string(),
+        Err(teloxide::RequestError::RetryAfter(seconds)) => {
+            format!("retry after {}", seconds.seconds())
+        }
+        Err(err) => err.to_string(),
+    };
+    // Other chats aren't limited
+    bot.send_message(ChatId(42), text).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_flood_control() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(flood_handler),
+    );
+    bot.dispatch_and_check_last_text("sent").await;
+
+    bot.enable_flood_control();
+    bot.dispatch_and_check_last_text("retry after 1").await;
+    let responses = bot.get_responses();
+    assert_eq!(responses.throttled_requests.len(), 1);
+    let throttled = &responses.throttled_requests[0];
+    assert_eq!(throttled.method, "SendMessage");
+    assert_eq!(throttled.chat_id, Some(MockUser::ID as i64));
+    assert_eq!(throttled.retry_after, 1);
+    assert_eq!(responses.sent_messages.len(), 2);
+}
+
+async fn failed_flood_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    // The message doesn't exist, so nothing is sent
+    let copied = bot
+        .copy_message(msg.chat.id, msg.chat.id, MessageId(404))
+        .await;
+    assert!(copied.is_err());
+    bot.send_message(msg.chat.id, "sent").await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_flood_control_failed_requests() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(failed_flood_handler),
+    );
+    bot.enable_flood_control();
+    bot.dispatch_and_check_last_text("sent").await;
+    assert!(bot.get_responses().throttled_requests.is_empty());
+}
+
+async fn concurrent_flood_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    let (first, second, third) = tokio::join!(
+        bot.send_message(msg.chat.id, "first"),
+        bot.send_message(msg.chat.id, "second"),
+        bot.send_message(msg.chat.id, "third"),
+    );
+    let sent = [first, second, third]
+        .into_iter()
+        .filter(|result| result.is_ok())
+        .count();
+    bot.send_message(ChatId(42), format!("sent {sent}")).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_flood_control_concurrent_requests() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(concurrent_flood_handler),
+    );
+    bot.enable_flood_control();
+    // Only one of the messages that were sent at the same time fits into the chat limit
+    bot.dispatch_and_check_last_text("sent 1").await;
+    assert_eq!(bot.get_responses().throttled_requests.len(), 2);
+}
+
+async fn missing_message_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    let missing = MessageId(404);
+    let results = [
+        bot.delete_message(msg.chat.id, missing).await.map(|_| ()),
+        bot.forward_message(msg.chat.id, msg.chat.id, missing)
+            .await
+            .map(|_| ()),
+        bot.copy_message(msg.chat.id, msg.chat.id, missing)
+            .await
+            .map(|_| ()),
+        bot.edit_message_caption(msg.chat.id, missing)
+            .caption("caption")
+            .await
+            .map(|_| ()),
+        bot.get_file(teloxide::types::FileId("missing".to_string()))
+            .await
+            .map(|_| ()),
+    ];
+    let errors: Vec<String> = results
+        .into_iter()
+        .map(|result| match result {
+            Ok(()) => "ok".to_string(),
+            Err(teloxide::RequestError::Api(err)) => format!("{err:?}"),
+            Err(err) => err.to_string(),
+        })
+        .collect();
+    bot.send_message(msg.chat.id, errors.join(" ")).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_telegram_errors() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(missing_message_handler),
+    );
+    bot.dispatch_and_check_last_text(
+        "MessageToDeleteNotFound MessageToForwardNotFound MessageToCopyNotFound \
+         MessageToEditNotFound FileIdInvalid",
+    )
+    .await;
+}
+
+async fn parse_mode_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    bot.send_message(msg.chat.id, "<b>Hello</b>, <i>world</i> &amp; 🦀")
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .await?;
+    let photo = InputFile::memory("somedata".to_string()).file_name("test.jpg");
+    bot.send_photo(msg.chat.id, photo)
+        .caption("*bold* caption\\!")
+        .parse_mode(teloxide::types::ParseMode::MarkdownV2)
+        .await?;
+    bot.send_poll(msg.chat.id, "<u>Question</u>?", ["A".into(), "B".into()])
+        .question_parse_mode(teloxide::types::ParseMode::Html)
+        .await?;
+    let result = bot
+        .send_message(msg.chat.id, "<b>unclosed")
+        .parse_mode(teloxide::types::ParseMode::Html)
+        .await;
+    if let Err(teloxide::RequestError::Api(teloxide::ApiError::CantParseEntities(error))) = result {
+        bot.send_message(msg.chat.id, error).await?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_parse_mode() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(parse_mode_handler),
+    );
+    bot.dispatch().await;
+
+    let responses = bot.get_responses();
+    let html = &responses.sent_messages_text[0].message;
+    assert_eq!(html.text(), Some("Hello, world & 🦀"));
+    assert_eq!(
+        html.entities().unwrap(),
+        [MessageEntity::bold(0, 5), MessageEntity::italic(7, 5)]
+    );
+
+    let photo = &responses.sent_messages_photo[0].message;
+    assert_eq!(photo.caption(), Some("bold caption!"));
+    assert_eq!(
+        photo.caption_entities().unwrap(),
+        [MessageEntity::bold(0, 4)]
+    );
+
+    let poll = responses.sent_messages_poll[0].message.poll().unwrap();
+    assert_eq!(poll.question, "Question?");
+    assert_eq!(
+        poll.question_entities.as_deref(),
+        Some(&[MessageEntity::underline(0, 8)][..])
+    );
+
+    assert_eq!(
+        responses.sent_messages.last().unwrap().text(),
+        Some(
+            "Bad Request: can't parse entities: Can't find end tag corresponding to start tag \
+             \"b\""
+        )
+    );
+}
+
+async fn limits_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    let photo = InputFile::memory("somedata".to_string()).file_name("test.jpg");
+    let huge_document = InputFile::memory(vec![0; 51 * 1024 * 1024]).file_name("huge.bin");
+    let results = [
+        bot.send_message(msg.chat.id, "a".repeat(4097))
+            .await
+            .map(|_| ()),
+        bot.send_photo(msg.chat.id, photo)
+            .caption("a".repeat(1025))
+            .await
+            .map(|_| ()),
+        bot.edit_message_caption(msg.chat.id, msg.id)
+            .caption("a".repeat(1025))
+            .await
+            .map(|_| ()),
+        bot.send_message(msg.chat.id, "button")
+            .reply_markup(InlineKeyboardMarkup::new([[
+                InlineKeyboardButton::callback("button", "a".repeat(65)),
+            ]]))
+            .await
+            .map(|_| ()),
+        bot.send_poll(
+            msg.chat.id,
+            "question",
+            (0..11).map(|i| i.to_string().into()),
+        )
+        .await
+        .map(|_| ()),
+        bot.send_document(msg.chat.id, huge_document)
+            .await
+            .map(|_| ()),
+    ];
+    let errors: Vec<String> = results
+        .into_iter()
+        .map(|result| match result {
+            Ok(()) => "ok".to_string(),
+            Err(teloxide::RequestError::Api(err)) => format!("{err:?}"),
+            Err(err) => err.to_string(),
+        })
+        .collect();
+    bot.send_message(msg.chat.id, errors.join("\n")).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_limits() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(limits_handler),
+    );
+    bot.dispatch_and_check_last_text(
+        "MessageIsTooLong\nUnknown(\"Bad Request: message caption is too long\")\n\
+         EditedMessageIsTooLong\nButtonDataInvalid\nPollCantHaveMoreOptions\nRequestEntityTooLarge",
+    )
+    .await;
+    assert_eq!(bot.get_responses().sent_messages.len(), 1);
+}
+
+async fn reply_keyboard_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    let reply_markup = match msg.text() {
+        Some("keyboard") => ReplyMarkup::Keyboard(
+            KeyboardMarkup::new([[KeyboardButton::new("Yes"), KeyboardButton::new("No")]])
+                .one_time_keyboard(),
+        ),
+        Some("question") => ReplyMarkup::force_reply(),
+        Some("remove") => ReplyMarkup::kb_remove(),
+        _ => return Ok(()),
+    };
+    bot.send_message(msg.chat.id, "ok")
+        .reply_markup(reply_markup)
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reply_keyboard() {
+    let mut bot = MockBot::new(
+        MockMessageText::new().text("keyboard"),
+        Update::filter_message().endpoint(reply_keyboard_handler),
+    );
+    let (chat_id, user_id) = (ChatId(MockUser::ID as i64), UserId(MockUser::ID));
+    bot.dispatch().await;
+    let keyboard = bot.reply_keyboard(chat_id, user_id).unwrap();
+    assert_eq!(keyboard.keyboard[0][0].text, "Yes");
+    // The bot didn't get to show it in other chats
+    assert!(bot.reply_keyboard(ChatId(1), user_id).is_none());
+
+    // Typing something else doesn't hide the one time keyboard, but pressing its button does
+    bot.update(MockMessageText::new().text("Maybe"));
+    bot.dispatch().await;
+    assert!(bot.reply_keyboard(chat_id, user_id).is_some());
+    bot.update(MockMessageText::new().text("Yes"));
+    bot.dispatch().await;
+    assert!(bot.reply_keyboard(chat_id, user_id).is_none());
+
+    bot.update(MockMessageText::new().text("question"));
+    bot.dispatch().await;
+    assert!(bot.force_reply(chat_id, user_id).is_some());
+    bot.update(MockMessageText::new().text("The answer"));
+    bot.dispatch().await;
+    assert!(bot.force_reply(chat_id, user_id).is_none());
+
+    bot.update(MockMessageText::new().text("keyboard"));
+    bot.dispatch().await;
+    bot.update(MockMessageText::new().text("remove"));
+    bot.dispatch().await;
+    assert!(bot.reply_keyboard(chat_id, user_id).is_none());
+}
+
+async fn inline_button_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    let keyboard = InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback("First", "first"),
+        InlineKeyboardButton::url("Link", "https://example.com".parse().unwrap()),
+    ]]);
+    bot.send_message(msg.chat.id, "Choose")
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+async fn press_button_handler(bot: Bot, query: CallbackQuery) -> ResponseResult<()> {
+    let message = query.regular_message().unwrap();
+    bot.edit_message_reply_markup(message.chat.id, message.id)
+        .reply_markup(InlineKeyboardMarkup::new([[
+            InlineKeyboardButton::callback("Second", "second"),
+        ]]))
+        .await?;
+    bot.answer_callback_query(query.id)
+        .text(format!("{} {}", query.data.unwrap(), query.from.id))
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_press_button() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        dptree::entry()
+            .branch(Update::filter_message().endpoint(inline_button_handler))
+            .branch(Update::filter_callback_query().endpoint(press_button_handler)),
+    );
+    bot.dispatch().await;
+    let sent_message = bot.get_responses().sent_messages.pop().unwrap();
+
+    bot.press_button(&sent_message, "First");
+    bot.dispatch().await;
+    let answer = bot.get_responses().answered_callback_queries.pop().unwrap();
+    assert_eq!(answer.text, Some(format!("first {}", MockUser::ID)));
+
+    // The keyboard is the edited one now
+    bot.press_button_at(&sent_message, 0, 0);
+    bot.dispatch().await;
+    let answer = bot.get_responses().answered_callback_queries.pop().unwrap();
+    assert_eq!(answer.text, Some(format!("second {}", MockUser::ID)));
+}
+
+#[tokio::test]
+async fn test_press_button_as() {
+    let mut bot = MockBot::new(
+        MockMessageText::new().chat(MockGroupChat::new().build()),
+        dptree::entry()
+            .branch(Update::filter_message().endpoint(inline_button_handler))
+            .branch(Update::filter_callback_query().endpoint(press_button_handler)),
+    );
+    bot.dispatch().await;
+    let sent_message = bot.get_responses().sent_messages.pop().unwrap();
+
+    let user = MockUser::new().id(42).build();
+    bot.press_button_as(&sent_message, user.clone(), "First");
+    bot.dispatch().await;
+    let answer = bot.get_responses().answered_callback_queries.pop().unwrap();
+    assert_eq!(answer.text.as_deref(), Some("first 42"));
+
+    bot.press_button_at_as(&sent_message, user, 0, 0);
+    bot.dispatch().await;
+    let answer = bot.get_responses().answered_callback_queries.pop().unwrap();
+    assert_eq!(answer.text.as_deref(), Some("second 42"));
+}
+
+async fn first_message_button_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    // Nothing was sent to this chat yet, so it is the message with id 1 there
+    let keyboard = InlineKeyboardMarkup::new([[InlineKeyboardButton::callback("First", "first")]]);
+    bot.send_message(ChatId(msg.chat.id.0 + 1), "Choose")
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+async fn message_id_handler(bot: Bot, query: CallbackQuery) -> ResponseResult<()> {
+    let message_id = query.regular_message().unwrap().id;
+    bot.answer_callback_query(query.id)
+        .text(message_id.to_string())
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_press_button_on_first_message() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        dptree::entry()
+            .branch(Update::filter_message().endpoint(first_message_button_handler))
+            .branch(Update::filter_callback_query().endpoint(message_id_handler)),
+    );
+    bot.dispatch().await;
+    let sent_message = bot.get_responses().sent_messages.pop().unwrap();
+    assert_eq!(sent_message.id, MessageId(1));
+
+    bot.press_button(&sent_message, "First");
+    bot.dispatch().await;
+    let answer = bot.get_responses().answered_callback_queries.pop().unwrap();
+    assert_eq!(answer.text.as_deref(), Some("1"));
+    assert_eq!(bot.chat_history(sent_message.chat.id).len(), 1);
+}
+
+#[tokio::test]
+#[should_panic(expected = "is not a callback button")]
+async fn test_press_url_button() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(inline_button_handler),
+    );
+    bot.dispatch().await;
+    let sent_message = bot.get_responses().sent_messages.pop().unwrap();
+    bot.press_button(&sent_message, "Link");
+}
+
+async fn keyboard_button_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    let text = match &msg.kind {
+        MessageKind::UsersShared(shared) => {
+            format!("users {}", shared.users_shared.users[0].user_id)
+        }
+        MessageKind::ChatShared(shared) => format!("chat {}", shared.chat_shared.chat_id),
+        _ if msg.contact().is_some() => format!("contact {}", msg.contact().unwrap().first_name),
+        _ if msg.location().is_some() => "location".to_string(),
+        _ if msg.poll().is_some() => format!("{:?}", msg.poll().unwrap().poll_type),
+        _ => msg.text().unwrap_or_default().to_string(),
+    };
+    let keyboard = KeyboardMarkup::new([
+        vec![
+            KeyboardButton::new("Text"),
+            KeyboardButton::new("Contact").request(ButtonRequest::Contact),
+            KeyboardButton::new("Location").request(ButtonRequest::Location),
+        ],
+        vec![
+            KeyboardButton::new("Quiz").request(ButtonRequest::Poll(KeyboardButtonPollType::Quiz)),
+            KeyboardButton::new("Users").request(ButtonRequest::RequestUsers(
+                KeyboardButtonRequestUsers::new(RequestId(1)),
+            )),
+            KeyboardButton::new("Chat").request(ButtonRequest::RequestChat(
+                KeyboardButtonRequestChat::new(RequestId(2), false),
+            )),
+        ],
+    ]);
+    bot.send_message(msg.chat.id, text)
+        .reply_markup(keyboard)
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_press_keyboard_button() {
+    let mut bot = MockBot::new(
+        MockMessageText::new().text("start"),
+        Update::filter_message().endpoint(keyboard_button_handler),
+    );
+    bot.dispatch().await;
+    let chat = bot.get_responses().sent_messages.pop().unwrap().chat;
+
+    for (button, expected) in [
+        ("Text", "Text".to_string()),
+        ("Contact", format!("contact {}", MockUser::FIRST_NAME)),
+        ("Location", "location".to_string()),
+        ("Quiz", "Quiz".to_string()),
+        (
+            "Users",
+            format!("users {}", MockMessageUsersShared::SHARED_USER_ID),
+        ),
+        (
+            "Chat",
+            format!("chat {}", MockMessageChatShared::SHARED_CHAT_ID),
+        ),
+    ] {
+        bot.press_keyboard_button(&chat, button);
+        bot.dispatch_and_check_last_text(&expected).await;
+    }
+}
+
+#[tokio::test]
+#[should_panic(expected = "There is no keyboard button")]
+async fn test_press_hidden_keyboard_button() {
+    let mut bot = MockBot::new(
+        MockMessageText::new().text("start"),
+        Update::filter_message().endpoint(keyboard_button_handler),
+    );
+    bot.dispatch().await;
+    // The keyboard was shown only in the private chat
+    bot.press_keyboard_button(&MockGroupChat::new().build(), "Text");
+}
+
+async fn chat_history_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    let first = bot.send_message(msg.chat.id, "First").await?;
+    let second = bot.send_message(msg.chat.id, "Second").await?;
+    bot.edit_message_text(msg.chat.id, first.id, "Edited")
+        .await?;
+    bot.delete_message(msg.chat.id, second.id).await?;
+    bot.send_message(msg.chat.id, "Third").await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chat_history() {
+    let mut bot = MockBot::new(
+        MockMessageText::new().text("start"),
+        Update::filter_message().endpoint(chat_history_handler),
+    );
+    bot.dispatch().await;
+
+    let chat_id = ChatId(MockUser::ID as i64);
+    let history = bot.chat_history(chat_id);
+    let texts: Vec<_> = history
+        .iter()
+        .map(|message| message.text().unwrap())
+        .collect();
+    assert_eq!(texts, ["start", "Edited", "Third"]);
+    let ids: Vec<_> = history.iter().map(|message| message.id.0).collect();
+    assert_eq!(ids, [1, 2, 4]);
+    // The responses still have the messages as they were sent
+    assert_eq!(bot.get_responses().sent_messages.len(), 3);
+
+    // Every chat counts its messages from the start
+    let group = MockGroupChat::new().build();
+    bot.update(MockMessageText::new().text("start").chat(group.clone()));
+    bot.dispatch().await;
+    let ids: Vec<_> = bot
+        .chat_history(group.id)
+        .iter()
+        .map(|message| message.id.0)
+        .collect();
+    assert_eq!(ids, [1, 2, 4]);
+    assert_eq!(bot.chat_history(chat_id).len(), 3);
+}
+
+async fn channel_username_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    // The channel posts are only there for the fake server to see the channel
+    let Some(username) = msg.text().filter(|text| text.starts_with('@')) else {
+        return Ok(());
+    };
+    let channel = Recipient::ChannelUsername(username.to_string());
+    match bot.send_message(channel.clone(), "Post").await {
+        Ok(_) => {
+            let photo = InputFile::memory("somedata".to_string()).file_name("test.jpg");
+            bot.send_photo(channel, photo).await?;
+        }
+        Err(teloxide::RequestError::Api(error)) => {
+            bot.send_message(msg.chat.id, format!("{error:?}")).await?;
+        }
+        Err(error) => return Err(error),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_channel_username() {
+    let channel = MockChannelChat::new().id(-1001).username("news").build();
+    let mut bot = MockBot::new(
+        // The fake server learns the usernames of the chats from the updates
+        MockMessageText::new().text("Hello").chat(channel),
+        Update::filter_message().endpoint(channel_username_handler),
+    );
+    bot.dispatch().await;
+
+    bot.update(MockMessageText::new().text("@News"));
+    bot.dispatch().await;
+    let responses = bot.get_responses();
+    assert_eq!(
+        responses.sent_messages_text[0].message.chat.id,
+        ChatId(-1001)
+    );
+    assert_eq!(
+        responses.sent_messages_photo[0].message.chat.id,
+        ChatId(-1001)
+    );
+
+    bot.add_chat_username("@other", ChatId(-1002));
+    bot.update(MockMessageText::new().text("@other"));
+    bot.dispatch().await;
+    let responses = bot.get_responses();
+    assert_eq!(
+        responses.sent_messages_text[0].message.chat.id,
+        ChatId(-1002)
+    );
+
+    bot.update(MockMessageText::new().text("@nobody"));
+    bot.dispatch_and_check_last_text("ChatNotFound").await;
+}
+
+#[tokio::test]
+async fn test_inject_fault_username() {
+    use server::faults::{Fault, FaultKind};
+
+    let mut bot = MockBot::new(
+        MockMessageText::new().text("@news"),
+        Update::filter_message().endpoint(channel_username_handler),
+    );
+    bot.add_chat_username("@news", ChatId(-1001));
+    // The username is resolved before the faults look at the chat
+    bot.inject_fault(
+        Fault::new(
+            "SendMessage",
+            FaultKind::Api(teloxide::ApiError::BotBlocked),
+        )
+        .chat_id(ChatId(-1001)),
+    );
+    bot.dispatch_and_check_last_text("BotBlocked").await;
+    assert!(bot.get_responses().sent_messages_photo.is_empty());
+}
+
+async fn strict_mode_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    let (command, id) = msg.text().unwrap().split_once(' ').unwrap();
+    let id: i64 = id.parse().unwrap();
+    let result = match command {
+        "send" => bot.send_message(ChatId(id), "Hi").await.map(|_| ()),
+        _ => bot
+            .ban_chat_member(msg.chat.id, UserId(id as u64))
+            .await
+            .map(|_| ()),
+    };
+    let text = match result {
+        Ok(()) => "ok".to_string(),
+        Err(teloxide::RequestError::Api(error)) => format!("{error:?}"),
+        Err(error) => return Err(error),
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_strict_mode() {
+    let group = MockGroupChat::new().build();
+    let mut bot = MockBot::new(
+        MockMessageText::new()
+            .text("send -1001")
+            .chat(group.clone()),
+        Update::filter_message().endpoint(strict_mode_handler),
+    );
+    // Without the strict mode any chat is fine
+    bot.dispatch_and_check_last_text("ok").await;
+
+    bot.enable_strict_mode();
+    bot.update(
+        MockMessageText::new()
+            .text("send -1002")
+            .chat(group.clone()),
+    );
+    bot.dispatch_and_check_last_text("ChatNotFound").await;
+    bot.update(MockMessageText::new().text("ban 42").chat(group.clone()));
+    bot.dispatch_and_check_last_text("UserNotFound").await;
+
+    // The chats can be added up front, and the messages sent there get their type and title
+    let channel = MockChannelChat::new().id(-1002).title("News").build();
+    bot.add_chat(channel.clone());
+    bot.add_user(MockUser::new().id(42).build());
+    bot.update(
+        MockMessageText::new()
+            .text("send -1002")
+            .chat(group.clone()),
+    );
+    bot.dispatch_and_check_last_text("ok").await;
+    assert_eq!(bot.get_responses().sent_messages[0].chat, channel);
+    bot.update(MockMessageText::new().text("ban 42").chat(group.clone()));
+    bot.dispatch_and_check_last_text("ok").await;
+
+    // The sender of the update is known. They go last, the messages of the banned users are not
+    // dispatched
+    let text = format!("ban {}", MockUser::ID);
+    bot.update(MockMessageText::new().text(&text).chat(group));
+    bot.dispatch_and_check_last_text("ok").await;
+}
+
+async fn permissions_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    let result = match msg.text() {
+        Some("pin") => bot.pin_chat_message(msg.chat.id, msg.id).await.map(|_| ()),
+        Some("mute") => bot
+            .restrict_chat_member(
+                msg.chat.id,
+                UserId(MockUser::ID),
+                ChatPermissions::SEND_MESSAGES,
+            )
+            .await
+            .map(|_| ()),
+        Some("delete") => bot.delete_message(msg.chat.id, msg.id).await.map(|_| ()),
+        _ => Ok(()),
+    };
+    let text = match result {
+        Ok(()) => "ok".to_string(),
+        Err(teloxide::RequestError::Api(error)) => format!("{error:?}"),
+        Err(error) => return Err(error),
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bot_permissions() {
+    let group = MockSupergroupChat::new().build();
+    let mut bot = MockBot::new(
+        MockMessageText::new().text("pin").chat(group.clone()),
+        Update::filter_message().endpoint(permissions_handler),
+    );
+    // Without the membership the bot can do anything
+    bot.dispatch_and_check_last_text("ok").await;
+
+    bot.set_bot_member(
+        group.id,
+        ChatMemberKind::Member(Member { until_date: None }),
+    );
+    bot.dispatch_and_check_last_text("NotEnoughRightsToPinMessage")
+        .await;
+    bot.update(MockMessageText::new().text("mute").chat(group.clone()));
+    bot.dispatch_and_check_last_text("NotEnoughRightsToRestrict")
+        .await;
+    bot.update(MockMessageText::new().text("delete").chat(group.clone()));
+    bot.dispatch_and_check_last_text("MessageCantBeDeleted")
+        .await;
+
+    let admin = Administrator {
+        custom_title: None,
+        is_anonymous: false,
+        can_be_edited: false,
+        can_manage_chat: true,
+        can_change_info: false,
+        can_post_messages: false,
+        can_edit_messages: false,
+        can_delete_messages: true,
+        can_post_stories: false,
+        can_edit_stories: false,
+        can_delete_stories: false,
+        can_manage_video_chats: false,
+        can_invite_users: false,
+        can_restrict_members: true,
+        can_pin_messages: false,
+        can_manage_topics: false,
+        can_promote_members: false,
+    };
+    bot.set_bot_member(group.id, ChatMemberKind::Administrator(admin));
+    bot.dispatch_and_check_last_text("ok").await;
+    bot.update(MockMessageText::new().text("pin").chat(group.clone()));
+    bot.dispatch_and_check_last_text("NotEnoughRightsToPinMessage")
+        .await;
+    bot.update(MockMessageText::new().text("mute").chat(group.clone()));
+    bot.dispatch_and_check_last_text("ok").await;
+
+    // The muted user can still write, but their photos don't reach the bot
+    bot.update(MockMessagePhoto::new().chat(group.clone()));
+    bot.dispatch().await;
+    assert!(bot.get_responses().sent_messages.is_empty());
+    bot.update(MockMessageText::new().text("hi").chat(group));
+    bot.dispatch_and_check_last_text("ok").await;
+}
+
+async fn share_handler(bot: Bot, msg: Message) -> ResponseResult<()> {
+    let forwarded = bot
+        .forward_message(msg.chat.id, msg.chat.id, msg.id)
+        .await
+        .map(|_| ());
+    let copied = bot
+        .copy_message(msg.chat.id, msg.chat.id, msg.id)
+        .await
+        .map(|_| ());
+    let mut results = vec![];
+    for result in [forwarded, copied] {
+        results.push(match result {
+            Ok(()) => "ok".to_string(),
+            // Telegram has no special errors for these, teloxide keeps their descriptions
+            Err(teloxide::RequestError::Api(teloxide::ApiError::Unknown(description))) => {
+                description
+            }
+            Err(error) => return Err(error),
+        });
+    }
+    bot.send_message(msg.chat.id, results.join("\n")).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_protected_content() {
+    let mut bot = MockBot::new(
+        MockMessageText::new(),
+        Update::filter_message().endpoint(share_handler),
+    );
+    bot.dispatch_and_check_last_text("ok\nok").await;
+
+    let mut protected = MockMessageText::new();
+    protected.has_protected_content = true;
+    bot.update(protected);
+    bot.dispatch_and_check_last_text(
+        "Bad Request: message has protected content and can't be forwarded\n\
+         Bad Request: message has protected content and can't be copied",
+    )
+    .await;
+
+    // Dice can be forwarded, but not copied
+    bot.update(MockMessageDice::new());
+    bot.dispatch_and_check_last_text("ok\nBad Request: message can't be copied")
+        .await;
+
+    // The quizzes can be copied only with their right answer
+    bot.update(MockMessagePoll::new().poll_type(PollType::Quiz));
+    bot.dispatch_and_check_last_text("ok\nBad Request: message can't be copied")
+        .await;
+    bot.update(
+        MockMessagePoll::new()
+            .poll_type(PollType::Quiz)
+            .correct_option_id(0),
+    );
+    bot.dispatch_and_check_last_text("ok\nok").await;
+
+    bot.update(MockMessageNewChatMembers::new().chat(MockGroupChat::new().build()));
+    bot.dispatch_and_check_last_text(
+        "Bad Request: message can't be forwarded\nBad Request: message can't be copied",
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn test_protect_content() {
+    let mut bot = MockBot::new(
+        MockMessageText::new().text("/copymessage"),
+        Update::filter_message().endpoint(|bot: Bot, msg: Message| async move {
+            let sent = bot
+                .send_message(msg.chat.id, "secret")
+                .protect_content(true)
+                .await?;
+            let result = bot.copy_message(msg.chat.id, msg.chat.id, sent.id).await;
+            assert!(result.is_err());
+            let result = bot.forward_message(msg.chat.id, msg.chat.id, sent.id).await;
+            assert!(result.is_err());
+            Ok::<_, teloxide::RequestError>(())
+        }),
+    );
+
+    bot.dispatch().await;
+
+    let responses = bot.get_responses();
+    assert_eq!(responses.sent_messages.len(), 1);
+    assert!(responses.copied_messages.is_empty());
+    assert!(responses.forwarded_messages.is_empty());
+}